@@ -488,6 +488,9 @@ async function createSiteList () {
       iconUrlInput.addEventListener('input', iconUrlValidation)
       iconUrlValidation.call(iconUrlInput)
 
+      // Manifest changes that require approval are only accepted after the user reviews them
+      let acceptManifestChanges = false
+
       // Handle form submission and validation
       submit.onclick = async (event) => {
         event.preventDefault()
@@ -540,7 +543,8 @@ async function createSiteList () {
             launch_on_login: document.getElementById('web-app-launch-on-login').checked,
             launch_on_browser: document.getElementById('web-app-launch-on-browser').checked,
             update_manifest: document.getElementById('web-app-update-manifest').checked,
-            update_icons: document.getElementById('web-app-update-icons').checked
+            update_icons: document.getElementById('web-app-update-icons').checked,
+            accept_manifest_changes: acceptManifestChanges
           }
         })
 
@@ -548,6 +552,22 @@ async function createSiteList () {
         if (response.type === 'Error') throw new Error(response.data)
         if (response.type !== 'SiteUpdated') throw new Error(`Received invalid response type: ${response.type}`)
 
+        // Show manifest changes that require approval and let the user accept them
+        const diff = response.data
+        if (!diff.applied && diff.changes.some(change => change.sensitive)) {
+          const changes = diff.changes
+            .filter(change => change.sensitive)
+            .map(change => `${change.field}${change.key ? ` ${change.key}` : ''}: ${change.old ?? '-'} → ${change.new ?? '-'}`)
+
+          document.getElementById('error-text').innerText = [await getMessage('managePageAppEditManifestChanges'), ...changes].join('\n')
+          Toast.getOrCreateInstance(document.getElementById('error-toast')).show()
+
+          acceptManifestChanges = true
+          submit.disabled = false
+          submit.innerText = await getMessage('buttonEditAcceptChanges')
+          return
+        }
+
         // Hide error toast
         Toast.getOrCreateInstance(document.getElementById('error-toast')).hide()
 
@@ -1151,8 +1171,8 @@ async function handleSettings (hasChanged = false) {
       document.getElementById('settings-always-patch-container').classList.add('d-none')
     }
 
-    // Obtain the config from the native program
-    const config = await getConfig()
+    // Obtain the config and locked keys from the native program
+    const { locked = [], ...config } = await getConfig()
 
     // Set settings values
     document.getElementById('settings-enable-wayland').checked = config.runtime_enable_wayland
@@ -1170,6 +1190,18 @@ async function handleSettings (hasChanged = false) {
     document.getElementById('settings-use-portals').disabled = false
     document.getElementById('settings-always-patch').disabled = false
 
+    // Disable settings locked by the system policy
+    for (const [key, id] of [
+      ['runtime_enable_wayland', 'settings-enable-wayland'],
+      ['runtime_use_xinput2', 'settings-use-xinput2'],
+      ['runtime_use_portals', 'settings-use-portals'],
+      ['always_patch', 'settings-always-patch']
+    ]) {
+      if (!locked.includes(key)) continue
+      document.getElementById(`${id}-container`).title = await getMessage('managePageSettingsLocked')
+      document.getElementById(id).disabled = true
+    }
+
     // Helper function to update config
     // Listen for enable Wayland changes
     document.getElementById('settings-enable-wayland').addEventListener('change', async function () {
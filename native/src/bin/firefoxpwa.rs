@@ -2,13 +2,16 @@ use std::process::exit;
 
 use anyhow::Result;
 use clap::Parser;
+use clap_complete::CompleteEnv;
 use log::{LevelFilter, error};
 use simplelog::{ColorChoice, Config, TermLogger, TerminalMode};
 
 #[rustfmt::skip]
-use firefoxpwa::console::{App, Run};
+use firefoxpwa::console::{App, Run, command};
 
 fn main() -> Result<()> {
+    CompleteEnv::with_factory(command).complete();
+
     TermLogger::init(LevelFilter::Info, Config::default(), TerminalMode::Mixed, ColorChoice::Auto)?;
 
     let app = App::parse();
@@ -0,0 +1,307 @@
+use anyhow::{Context, Result};
+use log::{info, warn};
+use reqwest::blocking::Client;
+use scraper::{ElementRef, Html, Selector};
+use serde::Serialize;
+use url::Url;
+
+use crate::storage::{CachedResponse, cached_fetch};
+
+const DOWNLOAD_ERROR: &str = "Failed to download web app page";
+const GENERATE_ERROR: &str = "Failed to generate web app manifest";
+
+/// URLs of a web app found from a page or manifest URL.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Discovery {
+    /// Direct URL of the web app manifest.
+    ///
+    /// For pages without a linked manifest, this is a data URL of
+    /// the manifest generated from the page metadata.
+    pub manifest_url: Url,
+
+    /// URL of the page that links to the manifest.
+    ///
+    /// Only set if the discovered URL was a page. The URL is
+    /// the final page URL, after following any redirects.
+    pub document_url: Option<Url>,
+}
+
+/// Finds the web app manifest from a page or manifest URL.
+///
+/// The URL is downloaded and, if it is an HTML page, the manifest is found
+/// from its `<link rel="manifest">` element, resolved against the `<base>`
+/// URL of the page. Other URLs are assumed to be direct manifest URLs.
+///
+/// If the page does not link to any manifest, the manifest is generated
+/// from the page metadata, so plain sites can also be installed.
+///
+/// URLs that respond with an unsuccessful status are rejected before they
+/// are parsed, so error pages are never installed as web apps.
+pub fn discover(url: &Url, client: &Client) -> Result<Discovery> {
+    info!("Checking the web app URL");
+    let request = client.get(url.to_owned()).build().context(DOWNLOAD_ERROR)?;
+    let response = cached_fetch(client, request).context(DOWNLOAD_ERROR)?;
+
+    if !is_html(&response) {
+        return Ok(Discovery { manifest_url: url.clone(), document_url: None });
+    }
+
+    info!("Searching the page for the web app manifest");
+    let document = Html::parse_document(&String::from_utf8_lossy(&response.body));
+    let base = base_url(&document, &response.url);
+
+    let selector = Selector::parse("link[rel~=manifest i][href]").unwrap();
+    let manifest_url = document
+        .select(&selector)
+        .filter_map(|link| link.value().attr("href"))
+        .find_map(|href| base.join(href.trim()).ok());
+
+    let manifest_url = match manifest_url {
+        Some(manifest_url) => {
+            info!("Found the web app manifest: {manifest_url}");
+            manifest_url
+        }
+        None => {
+            warn!("No web app manifest is linked from the page");
+            info!("Generating the web app manifest from the page metadata");
+            generate_manifest(&document, &base, &response.url).context(GENERATE_ERROR)?
+        }
+    };
+
+    Ok(Discovery { manifest_url, document_url: Some(response.url) })
+}
+
+/// Determines the manifest and document URLs of a web app.
+///
+/// If the document URL is set, the URL is used as a direct manifest URL.
+/// Otherwise, the URL is [discovered](discover), and the document URL
+/// defaults to the page URL or the result of parsing the URL with `.`.
+pub fn resolve_urls(url: &Url, document_url: Option<&Url>, client: &Client) -> Result<(Url, Url)> {
+    if let Some(document_url) = document_url {
+        return Ok((url.clone(), document_url.clone()));
+    }
+
+    let discovery = discover(url, client)?;
+    let document_url = match discovery.document_url {
+        Some(document_url) => document_url,
+        None => discovery.manifest_url.join(".")?,
+    };
+
+    Ok((discovery.manifest_url, document_url))
+}
+
+/// A web app manifest generated from the page metadata.
+#[derive(Serialize, Debug, Eq, PartialEq, Clone)]
+struct PageManifest {
+    start_url: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    theme_color: Option<String>,
+
+    icons: Vec<PageIcon>,
+}
+
+/// An icon of the web app manifest generated from the page metadata.
+#[derive(Serialize, Debug, Eq, PartialEq, Clone)]
+struct PageIcon {
+    src: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    r#type: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sizes: Option<String>,
+
+    purpose: &'static str,
+}
+
+/// Generates the manifest from the page metadata and returns it as a data URL.
+///
+/// The name is obtained from the `application-name` meta tag, Open Graph tags
+/// or the page title, and icons from icon links, including Apple touch and mask
+/// icons. If the page does not have any icon links, the Open Graph image and
+/// the default favicon location are used instead.
+fn generate_manifest(document: &Html, base: &Url, url: &Url) -> Result<Url> {
+    let meta = |selector: &str| {
+        let selector = Selector::parse(selector).unwrap();
+        document
+            .select(&selector)
+            .filter_map(|meta| meta.value().attr("content"))
+            .map(|content| content.trim().to_string())
+            .find(|content| !content.is_empty())
+    };
+
+    let title = Selector::parse("title").unwrap();
+    let title = document
+        .select(&title)
+        .map(|title| title.text().collect::<String>().trim().to_string())
+        .find(|title| !title.is_empty());
+
+    let name = meta("meta[name=application-name i]")
+        .or_else(|| meta("meta[property='og:site_name' i]"))
+        .or(title)
+        .or_else(|| meta("meta[property='og:title' i]"));
+
+    let description =
+        meta("meta[name=description i]").or_else(|| meta("meta[property='og:description' i]"));
+
+    let links = Selector::parse("link[rel][href]").unwrap();
+    let mut icons: Vec<PageIcon> = document
+        .select(&links)
+        .filter(|link| {
+            let rel = link.value().attr("rel").unwrap_or_default().to_lowercase();
+            rel.split_ascii_whitespace().any(|rel| rel.contains("icon"))
+        })
+        .filter_map(|link| page_icon(link, base))
+        .collect();
+
+    if icons.is_empty() {
+        let fallbacks = [meta("meta[property='og:image' i]"), Some("/favicon.ico".into())];
+        icons = fallbacks
+            .into_iter()
+            .flatten()
+            .filter_map(|src| base.join(&src).ok())
+            .map(|src| PageIcon { src: src.into(), r#type: None, sizes: None, purpose: "any" })
+            .collect();
+    }
+
+    let manifest = PageManifest {
+        start_url: url.to_string(),
+        name,
+        description,
+        theme_color: meta("meta[name=theme-color i]"),
+        icons,
+    };
+
+    let json = serde_json::to_string(&manifest)?;
+    let url = format!("data:application/manifest+json,{}", urlencoding::encode(&json));
+    Ok(Url::parse(&url)?)
+}
+
+/// Converts the icon link into a manifest icon.
+///
+/// Mask icons are always monochrome SVG icons, and sizes that
+/// are not valid in manifests are ignored.
+fn page_icon(link: ElementRef, base: &Url) -> Option<PageIcon> {
+    let link = link.value();
+    let src = base.join(link.attr("href")?.trim()).ok()?;
+    let mask = link.attr("rel")?.to_lowercase().contains("mask-icon");
+
+    let r#type = match link.attr("type") {
+        Some(r#type) if r#type.contains('/') => Some(r#type.to_string()),
+        Some(r#type) => Some(format!("image/{type}")),
+        None if mask => Some("image/svg+xml".into()),
+        None => None,
+    };
+
+    let sizes: Vec<&str> = link
+        .attr("sizes")
+        .unwrap_or_default()
+        .split_ascii_whitespace()
+        .filter(|size| {
+            size.eq_ignore_ascii_case("any")
+                || size.to_lowercase().split_once('x').is_some_and(|(width, height)| {
+                    [width, height].iter().all(|part| {
+                        !part.is_empty() && part.bytes().all(|byte| byte.is_ascii_digit())
+                    })
+                })
+        })
+        .collect();
+
+    let sizes = match sizes.is_empty() {
+        false => Some(sizes.join(" ")),
+        true if r#type.as_deref() == Some("image/svg+xml") => Some("any".into()),
+        true => None,
+    };
+
+    Some(PageIcon {
+        src: src.into(),
+        r#type,
+        sizes,
+        purpose: if mask { "monochrome" } else { "any" },
+    })
+}
+
+/// Checks whether the response is an HTML page.
+///
+/// Uses the content type if the server provided it,
+/// otherwise checks whether the body looks like markup.
+fn is_html(response: &CachedResponse) -> bool {
+    match &response.content_type {
+        Some(content_type) => content_type.contains("html"),
+        None => String::from_utf8_lossy(&response.body).trim_start().starts_with('<'),
+    }
+}
+
+/// Determines the base URL of the page.
+///
+/// Uses the first `<base>` element with the `href` attribute, as browsers
+/// do, and falls back to the page URL if it is missing or invalid.
+fn base_url(document: &Html, url: &Url) -> Url {
+    let selector = Selector::parse("base[href]").unwrap();
+
+    document
+        .select(&selector)
+        .next()
+        .and_then(|base| base.value().attr("href"))
+        .and_then(|href| url.join(href.trim()).ok())
+        .unwrap_or_else(|| url.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    use super::*;
+
+    const PAGE: &str = "<html><head><title>Example</title></head><body></body></html>";
+
+    /// Serves a single HTML response with the status on a local port.
+    fn serve(status: &'static str) -> Url {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 4096];
+            let _ = stream.read(&mut request);
+
+            let response = format!(
+                "HTTP/1.1 {status}\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{PAGE}",
+                PAGE.len()
+            );
+            stream.write_all(response.as_bytes()).unwrap();
+        });
+
+        Url::parse(&format!("http://{address}/")).unwrap()
+    }
+
+    fn client() -> Client {
+        Client::builder().no_proxy().build().unwrap()
+    }
+
+    #[test]
+    fn generates_manifest_for_page_without_manifest() {
+        let url = serve("200 OK");
+        let discovery = discover(&url, &client()).unwrap();
+
+        assert_eq!(discovery.manifest_url.scheme(), "data");
+        assert_eq!(discovery.document_url, Some(url));
+    }
+
+    #[test]
+    fn rejects_unsuccessful_page() {
+        for status in ["404 Not Found", "500 Internal Server Error"] {
+            let url = serve(status);
+            assert!(discover(&url, &client()).is_err(), "{status} page was accepted");
+        }
+    }
+}
@@ -0,0 +1,260 @@
+use std::fmt;
+
+use data_url::DataUrl;
+use log::info;
+use reqwest::blocking::Client;
+use serde::Serialize;
+use url::Url;
+use web_app_manifest::types::{ImagePurpose, Url as ManifestUrl};
+
+use crate::components::manifest::is_within_scope;
+use crate::components::site::{Site, SiteManifest};
+
+/// Schemes that web apps can register protocol handlers for without the `web+` prefix.
+///
+/// See: https://html.spec.whatwg.org/multipage/system-state.html#safelisted-scheme
+const SAFELISTED_SCHEMES: &[&str] = &[
+    "bitcoin",
+    "ftp",
+    "ftps",
+    "geo",
+    "im",
+    "irc",
+    "ircs",
+    "magnet",
+    "mailto",
+    "matrix",
+    "mms",
+    "news",
+    "nntp",
+    "openpgp4fpr",
+    "sftp",
+    "sip",
+    "sms",
+    "smsto",
+    "ssh",
+    "tel",
+    "urn",
+    "webcal",
+    "wtai",
+    "xmpp",
+];
+
+/// A severity of a manifest problem.
+#[derive(Serialize, Debug, Eq, PartialEq, Ord, PartialOrd, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum LintSeverity {
+    /// The problem is worked around, but the web app might not look or work as intended.
+    Warning,
+
+    /// The manifest cannot be installed, or the manifest feature cannot be used.
+    Error,
+}
+
+impl fmt::Display for LintSeverity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Warning => write!(f, "warning"),
+            Self::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A single problem found in the manifest.
+#[derive(Serialize, Debug, Eq, PartialEq, Clone)]
+pub struct LintFinding {
+    /// A severity of the problem.
+    pub severity: LintSeverity,
+
+    /// A manifest field with the problem, such as `scope` or `icons[1]`.
+    pub field: String,
+
+    /// A description of the problem.
+    pub message: String,
+}
+
+impl fmt::Display for LintFinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} [{}]: {}", self.severity, self.field, self.message)
+    }
+}
+
+/// All problems found in the manifest.
+#[derive(Serialize, Debug, Eq, PartialEq, Clone)]
+pub struct LintReport {
+    /// A direct URL of the checked manifest.
+    pub manifest_url: Url,
+
+    /// A document URL used to process the manifest.
+    pub document_url: Url,
+
+    /// All found problems, in the order of manifest fields.
+    pub findings: Vec<LintFinding>,
+}
+
+impl LintReport {
+    /// Creates a report for a manifest that could not be found.
+    ///
+    /// The report contains the failure as its only error.
+    pub fn failed(manifest_url: &Url, document_url: &Url, error: &anyhow::Error) -> Self {
+        let finding = LintFinding {
+            severity: LintSeverity::Error,
+            field: "manifest".into(),
+            message: format!("{error:#}"),
+        };
+
+        Self {
+            manifest_url: manifest_url.clone(),
+            document_url: document_url.clone(),
+            findings: vec![finding],
+        }
+    }
+
+    /// Whether any problem is an error.
+    #[inline]
+    pub fn has_errors(&self) -> bool {
+        self.findings.iter().any(|finding| finding.severity == LintSeverity::Error)
+    }
+}
+
+/// Checks the manifest for problems.
+///
+/// The manifest is downloaded and processed the same way as when installing
+/// a web app, so failures to download or parse it are also reported as errors.
+/// Icons are downloaded to check that they are reachable.
+pub fn lint(manifest_url: &Url, document_url: &Url, client: &Client) -> LintReport {
+    let mut report = LintReport {
+        manifest_url: manifest_url.clone(),
+        document_url: document_url.clone(),
+        findings: vec![],
+    };
+
+    let mut finding = |severity, field: &str, message: String| {
+        report.findings.push(LintFinding { severity, field: field.into(), message })
+    };
+
+    let manifest = match Site::fetch(manifest_url, document_url, client) {
+        Ok((manifest, _, _)) => manifest,
+        Err(error) => {
+            finding(LintSeverity::Error, "manifest", format!("{error:#}"));
+            return report;
+        }
+    };
+
+    info!("Checking the web app manifest");
+    check_names(&manifest, &mut finding);
+    check_scope(&manifest, &mut finding);
+    check_icons(&manifest, client, &mut finding);
+    check_protocol_handlers(&manifest, &mut finding);
+
+    report
+}
+
+fn check_names(manifest: &SiteManifest, finding: &mut impl FnMut(LintSeverity, &str, String)) {
+    let names = [("name", &manifest.name), ("short_name", &manifest.short_name)];
+    for (field, name) in names {
+        if name.as_ref().is_some_and(|name| name.trim().is_empty()) {
+            finding(LintSeverity::Warning, field, "Name is empty".into());
+        }
+    }
+
+    if names.iter().all(|(_, name)| name.as_ref().is_none_or(|name| name.trim().is_empty())) {
+        let message = "Manifest has no name or short name, so the domain will be used instead";
+        finding(LintSeverity::Warning, "name", message.into());
+    }
+}
+
+fn check_scope(manifest: &SiteManifest, finding: &mut impl FnMut(LintSeverity, &str, String)) {
+    let (start_url, scope) = match (&manifest.start_url, &manifest.scope) {
+        (ManifestUrl::Absolute(start_url), ManifestUrl::Absolute(scope)) => (start_url, scope),
+        (ManifestUrl::Absolute(_), _) => {
+            finding(LintSeverity::Error, "scope", "Scope is not a valid URL".into());
+            return;
+        }
+        _ => {
+            finding(LintSeverity::Error, "start_url", "Start URL is not a valid URL".into());
+            return;
+        }
+    };
+
+    if !is_within_scope(start_url, scope) {
+        let message = format!("Start URL {start_url} is not within the scope {scope}");
+        finding(LintSeverity::Error, "scope", message);
+    }
+}
+
+fn check_icons(
+    manifest: &SiteManifest,
+    client: &Client,
+    finding: &mut impl FnMut(LintSeverity, &str, String),
+) {
+    if !manifest.icons.iter().any(|icon| icon.purpose.contains(&ImagePurpose::Any)) {
+        let message = "Manifest has no icons with the \"any\" purpose, so the icon will be generated from the name";
+        finding(LintSeverity::Warning, "icons", message.into());
+    }
+
+    for (index, icon) in manifest.icons.iter().enumerate() {
+        let field = format!("icons[{index}]");
+
+        let ManifestUrl::Absolute(src) = &icon.src else {
+            finding(LintSeverity::Error, &field, "Icon source is not a valid URL".into());
+            continue;
+        };
+
+        if icon.sizes.is_empty() {
+            let message =
+                format!("Icon {src} has no usable sizes, so it is only used as a last resort");
+            finding(LintSeverity::Warning, &field, message);
+        }
+
+        if let Err(error) = check_reachable(src, client) {
+            finding(LintSeverity::Error, &field, format!("Icon {src} is not reachable: {error}"));
+        }
+    }
+}
+
+fn check_protocol_handlers(
+    manifest: &SiteManifest,
+    finding: &mut impl FnMut(LintSeverity, &str, String),
+) {
+    for (index, handler) in manifest.protocol_handlers.iter().enumerate() {
+        let field = format!("protocol_handlers[{index}]");
+        let protocol = handler.protocol.to_lowercase();
+
+        let custom = protocol.strip_prefix("web+").is_some_and(|name| {
+            !name.is_empty() && name.bytes().all(|byte| byte.is_ascii_lowercase())
+        });
+
+        if !custom && !SAFELISTED_SCHEMES.contains(&protocol.as_str()) {
+            let message = format!(
+                "Scheme {} is not safelisted and does not start with \"web+\" followed by letters",
+                handler.protocol
+            );
+            finding(LintSeverity::Error, &field, message);
+        }
+
+        match &handler.url {
+            ManifestUrl::Absolute(url) if !url.as_str().contains("%s") => {
+                let message = format!("Handler URL {url} does not contain the %s placeholder");
+                finding(LintSeverity::Error, &field, message);
+            }
+            ManifestUrl::Absolute(_) => {}
+            _ => finding(LintSeverity::Error, &field, "Handler URL is not a valid URL".into()),
+        }
+    }
+}
+
+/// Checks that the URL can be downloaded.
+fn check_reachable(url: &Url, client: &Client) -> Result<(), String> {
+    if url.scheme() == "data" {
+        let url = DataUrl::process(url.as_str()).map_err(|error| format!("{error:?}"))?;
+        url.decode_to_vec().map_err(|error| format!("{error:?}"))?;
+        return Ok(());
+    }
+
+    let response = client.get(url.clone()).send().map_err(|error| error.to_string())?;
+    match response.status() {
+        status if status.is_success() => Ok(()),
+        status => Err(format!("HTTP {status}")),
+    }
+}
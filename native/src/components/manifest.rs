@@ -0,0 +1,812 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use serde_with::{OneOrMany, Same};
+use url::Url;
+use web_app_manifest::types::Url as ManifestUrl;
+
+use crate::components::site::SiteManifest;
+
+/// A manifest field that is compared when updating a web app.
+#[derive(Serialize, Debug, Eq, PartialEq, Ord, PartialOrd, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ManifestField {
+    Id,
+    Name,
+    ShortName,
+    Scope,
+    StartUrl,
+    Icons,
+    ProtocolHandlers,
+    FileHandlers,
+    ShareTarget,
+    LaunchHandler,
+    Shortcuts,
+}
+
+impl fmt::Display for ManifestField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Id => write!(f, "identity"),
+            Self::Name => write!(f, "name"),
+            Self::ShortName => write!(f, "short name"),
+            Self::Scope => write!(f, "scope"),
+            Self::StartUrl => write!(f, "start URL"),
+            Self::Icons => write!(f, "icon"),
+            Self::ProtocolHandlers => write!(f, "protocol handler"),
+            Self::FileHandlers => write!(f, "file handler"),
+            Self::ShareTarget => write!(f, "share target"),
+            Self::LaunchHandler => write!(f, "launch handler"),
+            Self::Shortcuts => write!(f, "shortcut"),
+        }
+    }
+}
+
+/// A single difference between two manifests.
+///
+/// Fields with a single value have both the old and new value set,
+/// unless they were added or removed. List fields have a separate
+/// change for each added, removed or changed item.
+#[derive(Serialize, Debug, Eq, PartialEq, Clone)]
+pub struct ManifestChange {
+    /// A changed field.
+    pub field: ManifestField,
+
+    /// A key of the changed list item, such as a protocol or shortcut name.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+
+    /// An old value, if the field or item existed before.
+    pub old: Option<String>,
+
+    /// A new value, if the field or item still exists.
+    pub new: Option<String>,
+
+    /// Whether the change requires explicit approval before it is accepted.
+    ///
+    /// Changes of the identity mean the manifest belongs to a different
+    /// web app, changes of the scope and the start URL origin can redirect
+    /// the web app to a different site, and new protocol and file
+    /// handlers can make the web app handle links and files from
+    /// other programs.
+    pub sensitive: bool,
+}
+
+impl fmt::Display for ManifestChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let field = match &self.key {
+            Some(key) => format!("{} {key}", self.field),
+            None => self.field.to_string(),
+        };
+
+        match (&self.old, &self.new) {
+            (None, Some(new)) => write!(f, "Added {field}: {new}")?,
+            (Some(old), None) => write!(f, "Removed {field}: {old}")?,
+            (Some(old), Some(new)) => write!(f, "Changed {field}: {old} -> {new}")?,
+            (None, None) => write!(f, "Changed {field}")?,
+        }
+
+        if self.sensitive {
+            write!(f, " (requires approval)")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Differences between the current and the updated manifest of a web app.
+#[derive(Serialize, Debug, Eq, PartialEq, Clone, Default)]
+pub struct ManifestDiff {
+    /// All changes between the manifests.
+    pub changes: Vec<ManifestChange>,
+
+    /// Whether the updated manifest was accepted and stored.
+    ///
+    /// Updates with sensitive changes are not accepted unless approved.
+    pub applied: bool,
+
+    /// Whether the manifest changed since the web app last used it.
+    ///
+    /// Manifests with the same HTTP cache validator as the last time
+    /// are not parsed and compared again. Manifests without a validator
+    /// are only modified if any of their members changed.
+    pub modified: bool,
+}
+
+impl ManifestDiff {
+    /// Compares the current and the updated manifest.
+    pub fn new(old: &SiteManifest, new: &SiteManifest) -> Self {
+        let mut diff = Self::default();
+
+        diff.compare(ManifestField::Name, None, old.name.clone(), new.name.clone());
+        diff.compare(
+            ManifestField::ShortName,
+            None,
+            old.short_name.clone(),
+            new.short_name.clone(),
+        );
+        diff.compare(ManifestField::Scope, None, url_string(&old.scope), url_string(&new.scope));
+        diff.compare(
+            ManifestField::StartUrl,
+            None,
+            url_string(&old.start_url),
+            url_string(&new.start_url),
+        );
+
+        let old_icons = old.icons.iter().filter_map(|icon| url_string(&icon.src));
+        let new_icons = new.icons.iter().filter_map(|icon| url_string(&icon.src));
+        let old_icons: BTreeMap<_, _> = old_icons.map(|src| (src.clone(), src)).collect();
+        let new_icons: BTreeMap<_, _> = new_icons.map(|src| (src.clone(), src)).collect();
+        diff.compare_lists(ManifestField::Icons, &old_icons, &new_icons, false);
+
+        let old_handlers = old.protocol_handlers.iter().map(|handler| {
+            (handler.protocol.clone(), url_string(&handler.url).unwrap_or_default())
+        });
+        let new_handlers = new.protocol_handlers.iter().map(|handler| {
+            (handler.protocol.clone(), url_string(&handler.url).unwrap_or_default())
+        });
+        diff.compare_lists(
+            ManifestField::ProtocolHandlers,
+            &old_handlers.collect(),
+            &new_handlers.collect(),
+            true,
+        );
+
+        let old_shortcuts = old
+            .shortcuts
+            .iter()
+            .map(|shortcut| (shortcut.name.clone(), url_string(&shortcut.url).unwrap_or_default()));
+        let new_shortcuts = new
+            .shortcuts
+            .iter()
+            .map(|shortcut| (shortcut.name.clone(), url_string(&shortcut.url).unwrap_or_default()));
+        diff.compare_lists(
+            ManifestField::Shortcuts,
+            &old_shortcuts.collect(),
+            &new_shortcuts.collect(),
+            true,
+        );
+
+        diff
+    }
+
+    /// Also compares the manifest members that are not supported by the manifest parser.
+    ///
+    /// The identity is only compared when it was already stored, because
+    /// web apps installed before it was stored cannot know their old identity.
+    pub fn with_extras(mut self, old: &ManifestExtras, new: &ManifestExtras) -> Self {
+        if let Some(old_id) = &old.id {
+            let new_id = new.id.as_ref().map(Url::to_string);
+            self.compare(ManifestField::Id, None, Some(old_id.to_string()), new_id);
+        }
+
+        let file_types = |extras: &ManifestExtras| {
+            let handlers = extras.file_handlers.iter();
+            let types = handlers.flat_map(|handler| {
+                handler.accept.keys().map(|mime| (mime.clone(), handler.action.to_string()))
+            });
+            types.collect::<BTreeMap<_, _>>()
+        };
+
+        self.compare_lists(ManifestField::FileHandlers, &file_types(old), &file_types(new), true);
+
+        let share_target = |extras: &ManifestExtras| {
+            let target = extras.share_target.as_ref();
+            target.map(|target| format!("{} {}", target.method, target.action))
+        };
+
+        self.compare(ManifestField::ShareTarget, None, share_target(old), share_target(new));
+
+        let client_mode = |extras: &ManifestExtras| {
+            extras.launch_handler.as_ref().map(|handler| handler.client_mode.to_string())
+        };
+
+        self.compare(ManifestField::LaunchHandler, None, client_mode(old), client_mode(new));
+        self
+    }
+
+    /// Whether the manifests are the same in all compared fields.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Whether any change requires explicit approval.
+    #[inline]
+    pub fn requires_approval(&self) -> bool {
+        self.changes.iter().any(|change| change.sensitive)
+    }
+
+    fn compare(
+        &mut self,
+        field: ManifestField,
+        key: Option<&str>,
+        old: Option<String>,
+        new: Option<String>,
+    ) {
+        if old != new {
+            let sensitive = is_sensitive(field, &old, &new);
+            let key = key.map(Into::into);
+            self.changes.push(ManifestChange { field, key, old, new, sensitive });
+        }
+    }
+
+    /// Compares list items by their keys.
+    ///
+    /// Items of lists that are not keyed are identified by their values,
+    /// so they can only be added or removed. Their keys are not reported.
+    fn compare_lists(
+        &mut self,
+        field: ManifestField,
+        old: &BTreeMap<String, String>,
+        new: &BTreeMap<String, String>,
+        keyed: bool,
+    ) {
+        for (key, value) in old {
+            if !new.contains_key(key) {
+                self.compare(field, keyed.then_some(key), Some(value.clone()), None);
+            }
+        }
+
+        for (key, value) in new {
+            self.compare(field, keyed.then_some(key), old.get(key).cloned(), Some(value.clone()));
+        }
+    }
+}
+
+/// Checks whether the change of the field requires approval.
+fn is_sensitive(field: ManifestField, old: &Option<String>, new: &Option<String>) -> bool {
+    let origin = |url: &Option<String>| {
+        url.as_deref().and_then(|url| Url::parse(url).ok()).map(|url| url.origin())
+    };
+
+    match field {
+        ManifestField::Id | ManifestField::Scope => true,
+        ManifestField::StartUrl => origin(old) != origin(new),
+        ManifestField::ProtocolHandlers | ManifestField::FileHandlers => new.is_some(),
+        _ => false,
+    }
+}
+
+#[inline]
+fn url_string(url: &ManifestUrl) -> Option<String> {
+    match url {
+        ManifestUrl::Absolute(url) => Some(url.to_string()),
+        _ => None,
+    }
+}
+
+/// Checks whether the URL is within the scope.
+///
+/// See: https://www.w3.org/TR/appmanifest/#dfn-within-scope
+pub fn is_within_scope(url: &Url, scope: &Url) -> bool {
+    url.origin() == scope.origin() && url.path().starts_with(scope.path())
+}
+
+/// Manifest members that are not supported by the manifest parser.
+///
+/// They are parsed separately from the raw manifest, and stored next
+/// to the manifest with all URLs already resolved. Invalid members and
+/// items are ignored, same as browsers do.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Default)]
+#[serde(default)]
+pub struct ManifestExtras {
+    /// A unique identity of the web app.
+    ///
+    /// Resolved against the start URL, or the start URL itself
+    /// when the manifest does not specify a valid identity.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<Url>,
+
+    /// File types that the web app can open.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub file_handlers: Vec<FileHandlerResource>,
+
+    /// How the web app receives data shared from other programs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub share_target: Option<ShareTargetResource>,
+
+    /// How the web app is launched when it is already open.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub launch_handler: Option<LaunchHandlerResource>,
+}
+
+impl ManifestExtras {
+    /// Parses the members from the raw manifest.
+    ///
+    /// URLs are resolved against the manifest URL, which needs to be
+    /// the same one that was used to process the parsed manifest.
+    pub fn parse(json: &str, manifest: &SiteManifest, manifest_url: &Url) -> Self {
+        let Ok(Value::Object(raw)) = serde_json::from_str::<Value>(json) else {
+            return Self::default();
+        };
+
+        let scope = match &manifest.scope {
+            ManifestUrl::Absolute(scope) => scope,
+            _ => return Self::default(),
+        };
+
+        let id = match &manifest.start_url {
+            ManifestUrl::Absolute(start_url) => Some(parse_id(raw.get("id"), start_url)),
+            _ => None,
+        };
+
+        let items = |member: &str| {
+            let items = raw.get(member).and_then(Value::as_array).cloned();
+            items.unwrap_or_default().into_iter()
+        };
+
+        let file_handlers = items("file_handlers")
+            .filter_map(|item| serde_json::from_value::<RawFileHandler>(item).ok())
+            .filter_map(|handler| handler.process(manifest_url, scope))
+            .collect();
+
+        let share_target = raw
+            .get("share_target")
+            .and_then(|item| serde_json::from_value::<RawShareTarget>(item.clone()).ok())
+            .and_then(|target| target.process(manifest_url, scope));
+
+        let launch_handler = raw.get("launch_handler").and_then(Value::as_object).map(|handler| {
+            let client_mode = match handler.get("client_mode") {
+                Some(Value::Array(modes)) => {
+                    modes.iter().filter_map(Value::as_str).find_map(|mode| mode.parse().ok())
+                }
+                Some(Value::String(mode)) => mode.parse().ok(),
+                _ => None,
+            };
+
+            LaunchHandlerResource { client_mode: client_mode.unwrap_or_default() }
+        });
+
+        Self { id, file_handlers, share_target, launch_handler }
+    }
+}
+
+/// Processes the manifest identity.
+///
+/// See: https://www.w3.org/TR/appmanifest/#id-member
+fn parse_id(id: Option<&Value>, start_url: &Url) -> Url {
+    let id = id.and_then(Value::as_str).filter(|id| !id.is_empty());
+    let id = id.and_then(|id| start_url.join("/").ok()?.join(id).ok());
+
+    let mut id = match id {
+        Some(id) if id.origin() == start_url.origin() => id,
+        _ => start_url.clone(),
+    };
+
+    id.set_fragment(None);
+    id
+}
+
+/// How files are passed to the web app when multiple files are opened at once.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Copy, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum FileLaunchType {
+    /// All files are passed to a single web app window.
+    #[default]
+    SingleClient,
+
+    /// Each file is passed to a separate web app window.
+    MultipleClients,
+}
+
+/// A file handler of the web app.
+///
+/// See: https://wicg.github.io/manifest-incubations/#file_handlers-member
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
+pub struct FileHandlerResource {
+    /// A URL that is opened with the files.
+    pub action: Url,
+
+    /// A name of the file type.
+    pub name: Option<String>,
+
+    /// Accepted MIME types, mapped to their file extensions (including the leading `.`).
+    pub accept: BTreeMap<String, Vec<String>>,
+
+    /// How multiple files are passed to the web app.
+    #[serde(default)]
+    pub launch_type: FileLaunchType,
+}
+
+impl FileHandlerResource {
+    /// Checks whether any of the enabled MIME types accepts the file, based on its extension.
+    pub fn accepts(&self, filename: &str, enabled: &[String]) -> bool {
+        let filename = filename.to_lowercase();
+        let mut accept = self.accept.iter().filter(|(mime, _)| enabled.contains(mime));
+        accept.any(|(_, extensions)| {
+            extensions.iter().any(|extension| filename.ends_with(&extension.to_lowercase()))
+        })
+    }
+}
+
+/// A file handler as it is specified in the manifest.
+#[derive(Deserialize, Debug, Eq, PartialEq, Clone)]
+struct RawFileHandler {
+    action: String,
+
+    #[serde(default)]
+    name: Option<String>,
+
+    #[serde(default, with = "serde_with::As::<BTreeMap<Same, OneOrMany<Same>>>")]
+    accept: BTreeMap<String, Vec<String>>,
+
+    #[serde(default)]
+    launch_type: Option<String>,
+}
+
+impl RawFileHandler {
+    /// Resolves and validates the handler.
+    ///
+    /// Handlers with actions outside of the scope and without any
+    /// valid MIME types are ignored, as are invalid file extensions.
+    fn process(self, manifest_url: &Url, scope: &Url) -> Option<FileHandlerResource> {
+        let action = manifest_url.join(&self.action).ok()?;
+        if !is_within_scope(&action, scope) {
+            return None;
+        }
+
+        // MIME types and extensions are written to system files, so only allow safe characters
+        let valid = |value: &str| {
+            value.bytes().all(|byte| byte.is_ascii_alphanumeric() || b"!#$&^_.+-".contains(&byte))
+        };
+
+        let accept: BTreeMap<String, Vec<String>> = self
+            .accept
+            .into_iter()
+            .filter(|(mime, _)| match mime.split_once('/') {
+                Some((kind, subtype)) => {
+                    !kind.is_empty() && !subtype.is_empty() && valid(kind) && valid(subtype)
+                }
+                None => false,
+            })
+            .map(|(mime, extensions)| {
+                let extensions = extensions
+                    .into_iter()
+                    .filter(|extension| extension.len() > 1 && extension.starts_with('.'))
+                    .filter(|extension| valid(extension))
+                    .collect();
+                (mime.to_lowercase(), extensions)
+            })
+            .collect();
+
+        if accept.is_empty() {
+            return None;
+        }
+
+        let launch_type = match self.launch_type.as_deref() {
+            Some("multiple-clients") => FileLaunchType::MultipleClients,
+            _ => FileLaunchType::SingleClient,
+        };
+
+        Some(FileHandlerResource { action, name: self.name, accept, launch_type })
+    }
+}
+
+/// An HTTP method of the share target.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Copy, Default)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum ShareTargetMethod {
+    /// Shared data are passed in the query of the action URL.
+    #[default]
+    Get,
+
+    /// Shared data are passed in the request body.
+    Post,
+}
+
+impl fmt::Display for ShareTargetMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Get => write!(f, "GET"),
+            Self::Post => write!(f, "POST"),
+        }
+    }
+}
+
+/// An encoding of the share target request body.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Copy, Default)]
+pub enum ShareTargetEnctype {
+    /// Shared data are encoded as URL query parameters.
+    #[default]
+    #[serde(rename = "application/x-www-form-urlencoded")]
+    UrlEncoded,
+
+    /// Shared data are encoded as multipart form data, which also supports files.
+    #[serde(rename = "multipart/form-data")]
+    Multipart,
+}
+
+/// A file parameter of the share target.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
+pub struct ShareTargetFiles {
+    /// A name of the form field that contains the files.
+    pub name: String,
+
+    /// Accepted MIME types and file extensions (including the leading `.`).
+    pub accept: Vec<String>,
+}
+
+/// Names of the form fields that contain the shared data.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Default)]
+#[serde(default)]
+pub struct ShareTargetParams {
+    /// A name of the field that contains the shared title.
+    pub title: Option<String>,
+
+    /// A name of the field that contains the shared text.
+    pub text: Option<String>,
+
+    /// A name of the field that contains the shared URL.
+    pub url: Option<String>,
+
+    /// Fields that contain the shared files.
+    pub files: Vec<ShareTargetFiles>,
+}
+
+/// A share target of the web app.
+///
+/// See: https://w3c.github.io/web-share-target/#sharetarget-and-its-members
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
+pub struct ShareTargetResource {
+    /// A URL that receives the shared data.
+    pub action: Url,
+
+    /// An HTTP method of the request.
+    pub method: ShareTargetMethod,
+
+    /// An encoding of the request body.
+    pub enctype: ShareTargetEnctype,
+
+    /// Names of the form fields that contain the shared data.
+    pub params: ShareTargetParams,
+}
+
+/// A share target as it is specified in the manifest.
+#[derive(Deserialize, Debug, Eq, PartialEq, Clone)]
+struct RawShareTarget {
+    action: String,
+
+    #[serde(default)]
+    method: Option<String>,
+
+    #[serde(default)]
+    enctype: Option<String>,
+
+    #[serde(default)]
+    params: RawShareTargetParams,
+}
+
+#[derive(Deserialize, Debug, Eq, PartialEq, Clone, Default)]
+#[serde(default)]
+struct RawShareTargetParams {
+    title: Option<String>,
+    text: Option<String>,
+    url: Option<String>,
+
+    #[serde(with = "serde_with::As::<OneOrMany<Same>>")]
+    files: Vec<RawShareTargetFiles>,
+}
+
+#[derive(Deserialize, Debug, Eq, PartialEq, Clone)]
+struct RawShareTargetFiles {
+    name: String,
+
+    #[serde(default, with = "serde_with::As::<OneOrMany<Same>>")]
+    accept: Vec<String>,
+}
+
+impl RawShareTarget {
+    /// Resolves and validates the share target.
+    ///
+    /// Share targets with actions outside of the scope, unknown methods or
+    /// encodings, or files that cannot be sent with the encoding are ignored.
+    fn process(self, manifest_url: &Url, scope: &Url) -> Option<ShareTargetResource> {
+        let action = manifest_url.join(&self.action).ok()?;
+        if !is_within_scope(&action, scope) {
+            return None;
+        }
+
+        let method = match self.method.as_deref().map(str::to_uppercase).as_deref() {
+            None | Some("GET") => ShareTargetMethod::Get,
+            Some("POST") => ShareTargetMethod::Post,
+            Some(_) => return None,
+        };
+
+        let enctype = match self.enctype.as_deref().map(str::to_lowercase).as_deref() {
+            None | Some("application/x-www-form-urlencoded") => ShareTargetEnctype::UrlEncoded,
+            Some("multipart/form-data") if method == ShareTargetMethod::Post => {
+                ShareTargetEnctype::Multipart
+            }
+            Some(_) => return None,
+        };
+
+        let files: Vec<_> = self
+            .params
+            .files
+            .into_iter()
+            .filter(|files| !files.name.is_empty())
+            .map(|files| ShareTargetFiles { name: files.name, accept: files.accept })
+            .collect();
+
+        if !files.is_empty() && enctype != ShareTargetEnctype::Multipart {
+            return None;
+        }
+
+        let params = ShareTargetParams {
+            title: self.params.title.filter(|name| !name.is_empty()),
+            text: self.params.text.filter(|name| !name.is_empty()),
+            url: self.params.url.filter(|name| !name.is_empty()),
+            files,
+        };
+
+        Some(ShareTargetResource { action, method, enctype, params })
+    }
+}
+
+/// How the web app is launched when it is already open.
+///
+/// See: https://wicg.github.io/web-app-launch/#client_mode-member
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Copy, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum ClientMode {
+    /// The launch is handled according to the runtime settings.
+    #[default]
+    Auto,
+
+    /// The URL is always opened in a new web app window.
+    NavigateNew,
+
+    /// The most recently used web app window is navigated to the URL.
+    NavigateExisting,
+
+    /// The most recently used web app window is focused and receives
+    /// the URL through its launch queue, without being navigated.
+    FocusExisting,
+}
+
+impl fmt::Display for ClientMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Auto => write!(f, "auto"),
+            Self::NavigateNew => write!(f, "navigate-new"),
+            Self::NavigateExisting => write!(f, "navigate-existing"),
+            Self::FocusExisting => write!(f, "focus-existing"),
+        }
+    }
+}
+
+impl FromStr for ClientMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "auto" => Ok(Self::Auto),
+            "navigate-new" => Ok(Self::NavigateNew),
+            "navigate-existing" => Ok(Self::NavigateExisting),
+            "focus-existing" => Ok(Self::FocusExisting),
+            _ => Err(format!("Unknown client mode: {value}")),
+        }
+    }
+}
+
+/// A launch handler of the web app.
+///
+/// See: https://wicg.github.io/web-app-launch/#launch_handler-member
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Default)]
+pub struct LaunchHandlerResource {
+    /// How the web app is launched when it is already open.
+    pub client_mode: ClientMode,
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// Parses and processes the manifest the same way as when installing a web app.
+    fn parse(json: &Value) -> (SiteManifest, ManifestExtras) {
+        let manifest_url = Url::parse("https://example.com/app/manifest.json").unwrap();
+        let document_url = Url::parse("https://example.com/app/").unwrap();
+
+        let json = json.to_string();
+        let mut manifest: SiteManifest = serde_json::from_str(&json).unwrap();
+        manifest.process(&document_url, &manifest_url).unwrap();
+        let extras = ManifestExtras::parse(&json, &manifest, &manifest_url);
+
+        (manifest, extras)
+    }
+
+    fn manifest() -> Value {
+        json!({ "id": "app", "name": "Example", "start_url": "/app/", "scope": "/app/" })
+    }
+
+    fn with(member: &str, value: Value) -> Value {
+        let mut manifest = manifest();
+        manifest[member] = value;
+        manifest
+    }
+
+    fn diff(old: &Value, new: &Value) -> ManifestDiff {
+        let ((old, old_extras), (new, new_extras)) = (parse(old), parse(new));
+        ManifestDiff::new(&old, &new).with_extras(&old_extras, &new_extras)
+    }
+
+    #[test]
+    fn unchanged_manifest_has_no_changes() {
+        let diff = diff(&manifest(), &manifest());
+        assert!(diff.is_empty());
+        assert!(!diff.requires_approval());
+    }
+
+    #[test]
+    fn name_change_is_applied_silently() {
+        let diff = diff(&manifest(), &with("name", json!("Other")));
+
+        assert_eq!(diff.changes.len(), 1);
+        assert_eq!(diff.changes[0].field, ManifestField::Name);
+        assert_eq!(diff.changes[0].old.as_deref(), Some("Example"));
+        assert_eq!(diff.changes[0].new.as_deref(), Some("Other"));
+        assert!(!diff.requires_approval());
+    }
+
+    #[test]
+    fn scope_change_requires_approval() {
+        let diff = diff(&manifest(), &with("scope", json!("/")));
+
+        assert!(diff.changes.iter().any(|change| change.field == ManifestField::Scope));
+        assert!(diff.requires_approval());
+    }
+
+    #[test]
+    fn start_url_change_requires_approval_across_origins() {
+        let diff = diff(&manifest(), &with("start_url", json!("/app/home")));
+        assert!(diff.changes.iter().any(|change| change.field == ManifestField::StartUrl));
+        assert!(!diff.requires_approval());
+
+        let old = Some("https://example.com/app/".to_string());
+        let same = Some("https://example.com/app/home".to_string());
+        let other = Some("https://example.org/app/".to_string());
+        assert!(!is_sensitive(ManifestField::StartUrl, &old, &same));
+        assert!(is_sensitive(ManifestField::StartUrl, &old, &other));
+    }
+
+    #[test]
+    fn added_protocol_handler_requires_approval() {
+        let handlers = json!([{ "protocol": "web+example", "url": "/app/?uri=%s" }]);
+        let with_handler = with("protocol_handlers", handlers);
+
+        let added = diff(&manifest(), &with_handler);
+        assert_eq!(added.changes.len(), 1);
+        assert_eq!(added.changes[0].field, ManifestField::ProtocolHandlers);
+        assert_eq!(added.changes[0].key.as_deref(), Some("web+example"));
+        assert!(added.requires_approval());
+
+        let removed = diff(&with_handler, &manifest());
+        assert_eq!(removed.changes.len(), 1);
+        assert!(!removed.requires_approval());
+    }
+
+    #[test]
+    fn added_file_handler_requires_approval() {
+        let handlers = json!([{ "action": "/app/open", "accept": { "text/plain": [".txt"] } }]);
+        let diff = diff(&manifest(), &with("file_handlers", handlers));
+
+        assert_eq!(diff.changes.len(), 1);
+        assert_eq!(diff.changes[0].field, ManifestField::FileHandlers);
+        assert!(diff.requires_approval());
+    }
+
+    #[test]
+    fn identity_change_requires_approval() {
+        let diff = diff(&manifest(), &with("id", json!("other")));
+
+        assert_eq!(diff.changes.len(), 1);
+        assert_eq!(diff.changes[0].field, ManifestField::Id);
+        assert!(diff.requires_approval());
+    }
+}
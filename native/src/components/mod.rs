@@ -1,6 +1,10 @@
 #[cfg(platform_windows)]
 pub mod _7zip;
 
+pub mod discovery;
+pub mod lint;
+pub mod manifest;
 pub mod profile;
 pub mod runtime;
+pub mod share;
 pub mod site;
@@ -1,13 +1,20 @@
-use std::fs::{create_dir_all, remove_dir_all};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{create_dir_all, read_dir, read_to_string, remove_dir_all, rename, write};
+use std::path::Path;
 
 use anyhow::{Context, Result};
 use fs_extra::dir::{CopyOptions, copy};
-use log::info;
+use log::{info, warn};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use ulid::Ulid;
+use url::Origin;
 
 use crate::directories::ProjectDirs;
 
+const PREFS_SECTION_START: &str = "// BEGIN FIREFOXPWA MANAGED PREFERENCES";
+const PREFS_SECTION_END: &str = "// END FIREFOXPWA MANAGED PREFERENCES";
+
 #[non_exhaustive]
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
 pub struct Profile {
@@ -31,6 +38,26 @@ pub struct Profile {
     /// A list of web app IDs installed within this profile.
     #[serde(default)]
     pub sites: Vec<Ulid>,
+
+    /// Arguments to be passed to the Firefox runtime for web apps in this profile.
+    ///
+    /// Passed after the global arguments and before the web app arguments.
+    #[serde(default)]
+    pub arguments: Vec<String>,
+
+    /// Environment variables to be passed to the Firefox runtime for web apps in this profile.
+    ///
+    /// Take precedence over the global variables, but not over the web app variables.
+    #[serde(default)]
+    pub variables: BTreeMap<String, String>,
+
+    /// Firefox preferences of this profile.
+    ///
+    /// Written to a managed section of the profile's `user.js` file, so
+    /// they are applied every time a web app in this profile is launched.
+    /// Values must be booleans, integers or strings.
+    #[serde(default)]
+    pub prefs: BTreeMap<String, Value>,
 }
 
 impl Default for Profile {
@@ -41,6 +68,9 @@ impl Default for Profile {
             name: Some("Default".into()),
             description: Some("Default profile for all web apps".into()),
             sites: vec![],
+            arguments: vec![],
+            variables: BTreeMap::new(),
+            prefs: BTreeMap::new(),
         }
     }
 }
@@ -48,7 +78,7 @@ impl Default for Profile {
 impl Profile {
     #[inline]
     pub fn new(name: Option<String>, description: Option<String>) -> Self {
-        Self { ulid: Ulid::new(), name, description, sites: vec![] }
+        Self { ulid: Ulid::new(), name, description, ..Self::default() }
     }
 
     pub fn patch(&self, dirs: &ProjectDirs) -> Result<()> {
@@ -59,17 +89,217 @@ impl Profile {
         options.content_only = true;
         options.overwrite = true;
 
-        if !profile.exists() {
-            info!("Creating a profile directory");
-            create_dir_all(&profile).context("Failed to create a profile directory")?;
-        }
+        create_profile_directory(&profile)?;
 
         info!("Patching the profile");
         let _ = remove_dir_all(profile.join("startupCache"));
         let _ = remove_dir_all(profile.join("chrome/pwa"));
-        copy(source, profile, &options).context("Failed to patch the profile")?;
+        copy(source, &profile, &options).context("Failed to patch the profile")?;
+        self.write_prefs(dirs)?;
 
         info!("Profile patched!");
         Ok(())
     }
+
+    /// Writes the profile preferences to the profile's `user.js` file.
+    ///
+    /// Preferences are stored in a separate section that is replaced on
+    /// every write, so other preferences in the file are left untouched.
+    ///
+    /// Firefox copies values from `user.js` to `prefs.js`, where they stay
+    /// after being removed from `user.js`. Preferences that were previously
+    /// managed, but are not anymore, are therefore also cleared from `prefs.js`,
+    /// so they return to their default values on the next launch.
+    pub fn write_prefs(&self, dirs: &ProjectDirs) -> Result<()> {
+        const PREFS_WRITE_ERROR: &str = "Failed to write profile preferences";
+
+        let profile = dirs.userdata.join("profiles").join(self.ulid.to_string());
+        let filename = profile.join("user.js");
+
+        if self.prefs.is_empty() && !filename.exists() {
+            return Ok(());
+        }
+
+        let content = if filename.exists() {
+            read_to_string(&filename).context(PREFS_WRITE_ERROR)?
+        } else {
+            String::new()
+        };
+
+        // Remove the previous managed section and keep everything else
+        let start = content.find(PREFS_SECTION_START);
+        let end = content.find(PREFS_SECTION_END).map(|end| end + PREFS_SECTION_END.len());
+        let mut removed = BTreeSet::new();
+        let mut content = match (start, end) {
+            (Some(start), Some(end)) if start < end => {
+                removed.extend(content[start..end].lines().filter_map(pref_name));
+                let end = if content[end..].starts_with('\n') { end + 1 } else { end };
+                format!("{}{}", &content[..start], &content[end..])
+            }
+            _ => content,
+        };
+
+        if !self.prefs.is_empty() {
+            if !content.is_empty() && !content.ends_with('\n') {
+                content.push('\n');
+            }
+
+            content.push_str(PREFS_SECTION_START);
+            content.push('\n');
+
+            for (name, value) in &self.prefs {
+                if !(value.is_boolean() || value.is_i64() || value.is_string()) {
+                    warn!("Skipping preference {name} with unsupported value {value}");
+                    continue;
+                }
+
+                let name = Value::from(name.as_str());
+                content.push_str(&format!("user_pref({name}, {value});\n"));
+            }
+
+            content.push_str(PREFS_SECTION_END);
+            content.push('\n');
+        }
+
+        create_profile_directory(&profile)?;
+        write(filename, content).context(PREFS_WRITE_ERROR)?;
+
+        removed.retain(|name| !self.prefs.contains_key(name));
+        clear_prefs(&profile, &removed).context(PREFS_WRITE_ERROR)
+    }
+
+    /// Moves the stored data of the origin to another profile.
+    ///
+    /// Only the origin storage, such as local storage, IndexedDB and caches, is moved.
+    /// Cookies and permissions are stored in databases of the whole profile, so they
+    /// stay in this profile. Data that already exist in the target profile are kept.
+    /// Returns the number of moved storage directories.
+    pub fn move_origin_data(
+        &self,
+        dirs: &ProjectDirs,
+        origin: &Origin,
+        target: &Profile,
+    ) -> Result<usize> {
+        const DATA_MOVE_ERROR: &str = "Failed to move web app data";
+
+        let source = dirs.userdata.join("profiles").join(self.ulid.to_string()).join("storage");
+        let target = dirs.userdata.join("profiles").join(target.ulid.to_string()).join("storage");
+
+        // Firefox replaces characters that are not allowed in file names with `+`
+        // Directories with origin attributes have them appended after `^`
+        let name: String = origin
+            .ascii_serialization()
+            .chars()
+            .map(|char| if r#":/*?"<>|\"#.contains(char) { '+' } else { char })
+            .collect();
+
+        let mut moved = 0;
+
+        for repository in ["default", "permanent", "temporary"] {
+            let Ok(entries) = read_dir(source.join(repository)) else { continue };
+
+            for entry in entries.flatten() {
+                let filename = entry.file_name().to_string_lossy().to_string();
+                if filename != name && !filename.starts_with(&format!("{name}^")) {
+                    continue;
+                }
+
+                let destination = target.join(repository).join(&filename);
+                if destination.exists() {
+                    warn!(
+                        "Skipping {repository}/{filename}, because it already exists in the target profile"
+                    );
+                    continue;
+                }
+
+                create_dir_all(target.join(repository)).context(DATA_MOVE_ERROR)?;
+                rename(entry.path(), destination).context(DATA_MOVE_ERROR)?;
+                moved += 1;
+            }
+        }
+
+        Ok(moved)
+    }
+}
+
+/// Parses the preference name from a `user_pref` line.
+fn pref_name(line: &str) -> Option<String> {
+    let rest = line.trim().strip_prefix("user_pref(")?;
+    serde_json::Deserializer::from_str(rest).into_iter::<String>().next()?.ok()
+}
+
+/// Removes preferences from the profile's `prefs.js` file.
+///
+/// Firefox rewrites this file when it exits, so changes made
+/// while the profile is in use are overwritten.
+fn clear_prefs(profile: &Path, names: &BTreeSet<String>) -> Result<()> {
+    let filename = profile.join("prefs.js");
+
+    if names.is_empty() || !filename.exists() {
+        return Ok(());
+    }
+
+    let content = read_to_string(&filename)?;
+    let content: String = content
+        .lines()
+        .filter(|line| pref_name(line).is_none_or(|name| !names.contains(&name)))
+        .map(|line| format!("{line}\n"))
+        .collect();
+
+    write(filename, content)?;
+    Ok(())
+}
+
+fn create_profile_directory(profile: &Path) -> Result<()> {
+    if !profile.exists() {
+        info!("Creating a profile directory");
+        create_dir_all(profile).context("Failed to create a profile directory")?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn removed_prefs_are_cleared_from_firefox_prefs() -> Result<()> {
+        let directory = tempdir()?;
+        let dirs = ProjectDirs {
+            executables: directory.path().into(),
+            sysdata: directory.path().into(),
+            userdata: directory.path().into(),
+        };
+
+        let mut profile = Profile::new(None, None);
+        profile.prefs.insert("browser.tabs.warnOnClose".into(), json!(false));
+        profile.prefs.insert("general.smoothScroll".into(), json!(true));
+        profile.write_prefs(&dirs)?;
+
+        // Firefox copies applied preferences to the prefs.js file
+        let directory = dirs.userdata.join("profiles").join(profile.ulid.to_string());
+        let prefs = concat!(
+            "user_pref(\"browser.tabs.warnOnClose\", false);\n",
+            "user_pref(\"general.smoothScroll\", true);\n",
+            "user_pref(\"browser.startup.page\", 3);\n",
+        );
+        write(directory.join("prefs.js"), prefs)?;
+
+        profile.prefs.remove("browser.tabs.warnOnClose");
+        profile.write_prefs(&dirs)?;
+
+        let user = read_to_string(directory.join("user.js"))?;
+        assert!(!user.contains("browser.tabs.warnOnClose"));
+        assert!(user.contains("general.smoothScroll"));
+
+        let prefs = read_to_string(directory.join("prefs.js"))?;
+        assert!(!prefs.contains("browser.tabs.warnOnClose"));
+        assert!(prefs.contains("general.smoothScroll"));
+        assert!(prefs.contains("browser.startup.page"));
+        Ok(())
+    }
 }
@@ -247,9 +247,11 @@ impl Runtime {
             use crate::storage::Storage;
 
             let dirs = ProjectDirs::new()?;
-            let mut storage = Storage::load(&dirs)?;
+            let _lock = Storage::lock(&dirs)?;
+            let mut storage = Storage::load_index(&dirs)?;
 
             if storage.config.use_linked_runtime {
+                storage.policies.ensure_unlocked("use_linked_runtime")?;
                 self.uninstall()?;
             }
 
@@ -349,7 +351,12 @@ impl Runtime {
         use crate::storage::Storage;
 
         let dirs = ProjectDirs::new()?;
-        let mut storage = Storage::load(&dirs)?;
+        let _lock = Storage::lock(&dirs)?;
+        let mut storage = Storage::load_index(&dirs)?;
+
+        if !storage.config.use_linked_runtime {
+            storage.policies.ensure_unlocked("use_linked_runtime")?;
+        }
 
         self.uninstall()?;
 
@@ -0,0 +1,205 @@
+use std::fs::{create_dir_all, read};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use image::ImageFormat;
+use ulid::Ulid;
+use url::Url;
+use url::form_urlencoded::Serializer;
+
+use crate::components::manifest::{ShareTargetEnctype, ShareTargetMethod, ShareTargetResource};
+use crate::components::site::Site;
+use crate::directories::ProjectDirs;
+
+const READ_FILE_ERROR: &str = "Failed to read shared file";
+const WRITE_REQUEST_ERROR: &str = "Failed to prepare share request";
+
+/// Data shared with a web app.
+#[derive(Debug, Eq, PartialEq, Clone, Default)]
+pub struct ShareData {
+    pub title: Option<String>,
+    pub text: Option<String>,
+    pub url: Option<Url>,
+    pub files: Vec<PathBuf>,
+}
+
+/// A request that passes shared data to the share target.
+///
+/// See: https://w3c.github.io/web-share-target/#invoking-the-share-target
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum ShareRequest {
+    /// Shared data are passed in the query of the URL.
+    Get(Url),
+
+    /// Shared data are passed in the request body.
+    Post { url: Url, content_type: String, body: Vec<u8> },
+}
+
+impl ShareRequest {
+    /// Builds the request described by the share target of the web app.
+    ///
+    /// When the share target does not accept URLs, the shared URL is added
+    /// to the text instead. Files are matched to file parameters by their
+    /// extensions and MIME types, which are guessed from the extension.
+    pub fn new(site: &Site, target: &ShareTargetResource, data: &ShareData) -> Result<Self> {
+        let mut text = data.text.clone();
+        let mut url = data.url.as_ref().map(Url::to_string);
+
+        if target.params.url.is_none()
+            && let Some(url) = url.take()
+        {
+            text = Some(match text {
+                Some(text) => format!("{text} {url}"),
+                None => url,
+            });
+        }
+
+        let fields: Vec<(&str, String)> = [
+            (&target.params.title, data.title.clone()),
+            (&target.params.text, text),
+            (&target.params.url, url),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name.as_deref()?, value?)))
+        .collect();
+
+        if !data.files.is_empty() && target.params.files.is_empty() {
+            bail!("Web app does not accept shared files");
+        }
+
+        let mut action = target.action.clone();
+
+        match (target.method, target.enctype) {
+            (ShareTargetMethod::Get, _) => {
+                let query = Serializer::new(String::new()).extend_pairs(fields).finish();
+                action.set_query(if query.is_empty() { None } else { Some(&query) });
+                Ok(Self::Get(action))
+            }
+
+            (ShareTargetMethod::Post, ShareTargetEnctype::UrlEncoded) => {
+                let body = Serializer::new(String::new()).extend_pairs(fields).finish();
+                let content_type = "application/x-www-form-urlencoded".into();
+                Ok(Self::Post { url: action, content_type, body: body.into_bytes() })
+            }
+
+            (ShareTargetMethod::Post, ShareTargetEnctype::Multipart) => {
+                let boundary = format!("----FirefoxPWAShare{}", Ulid::new());
+                let mut body = vec![];
+
+                for (name, value) in fields {
+                    write!(body, "--{boundary}\r\n")?;
+                    write!(
+                        body,
+                        "Content-Disposition: form-data; name=\"{}\"\r\n\r\n",
+                        escape(name)
+                    )?;
+                    write!(body, "{value}\r\n")?;
+                }
+
+                for file in &data.files {
+                    let filename = file.file_name().unwrap_or_default().to_string_lossy();
+                    let mime = guess_type(site, file);
+
+                    let params = target.params.files.iter().find(|params| {
+                        params.accept.iter().any(|accept| accepts(accept, &filename, &mime))
+                    });
+                    let Some(params) = params else {
+                        bail!("File {} is not accepted by the web app", file.display());
+                    };
+
+                    let content = read(file).context(READ_FILE_ERROR)?;
+                    write!(body, "--{boundary}\r\n")?;
+                    write!(
+                        body,
+                        "Content-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\n",
+                        escape(&params.name),
+                        escape(&filename)
+                    )?;
+                    write!(body, "Content-Type: {mime}\r\n\r\n")?;
+                    body.extend_from_slice(&content);
+                    write!(body, "\r\n")?;
+                }
+
+                write!(body, "--{boundary}--\r\n")?;
+
+                let content_type = format!("multipart/form-data; boundary={boundary}");
+                Ok(Self::Post { url: action, content_type, body })
+            }
+        }
+    }
+
+    /// A URL that the web app is launched on.
+    pub fn url(&self) -> &Url {
+        match self {
+            Self::Get(url) | Self::Post { url, .. } => url,
+        }
+    }
+
+    /// Stores the request body for the runtime.
+    ///
+    /// The file is stored in the `shares` directory inside the user data
+    /// directory, which is the only place the runtime loads requests from.
+    /// It contains the content type on the first line, followed by the body,
+    /// and is removed by the runtime once it is loaded. Returns nothing for
+    /// requests without a body.
+    pub fn persist(&self, dirs: &ProjectDirs) -> Result<Option<PathBuf>> {
+        let Self::Post { content_type, body, .. } = self else { return Ok(None) };
+
+        let directory = dirs.userdata.join("shares");
+        create_dir_all(&directory).context(WRITE_REQUEST_ERROR)?;
+
+        let mut file = tempfile::Builder::new()
+            .prefix("share-")
+            .tempfile_in(directory)
+            .context(WRITE_REQUEST_ERROR)?;
+
+        writeln!(file, "{content_type}").context(WRITE_REQUEST_ERROR)?;
+        file.write_all(body).context(WRITE_REQUEST_ERROR)?;
+
+        let (_, path) = file.keep().context(WRITE_REQUEST_ERROR)?;
+        Ok(Some(path))
+    }
+}
+
+/// Guesses the MIME type of the file from its extension.
+///
+/// Uses types from file handlers of the web app and known image formats,
+/// and falls back to a generic binary type.
+fn guess_type(site: &Site, file: &Path) -> String {
+    let filename = file.file_name().unwrap_or_default().to_string_lossy().to_lowercase();
+
+    let handlers = site.file_handlers().iter().flat_map(|handler| &handler.accept);
+    let mut types = handlers.filter(|(_, extensions)| {
+        extensions.iter().any(|extension| filename.ends_with(&extension.to_lowercase()))
+    });
+
+    if let Some((mime, _)) = types.next() {
+        return mime.clone();
+    }
+
+    match ImageFormat::from_path(file) {
+        Ok(format) => format.to_mime_type().into(),
+        Err(_) => "application/octet-stream".into(),
+    }
+}
+
+/// Checks whether the accepted MIME type or extension matches the file.
+fn accepts(accept: &str, filename: &str, mime: &str) -> bool {
+    let accept = accept.to_lowercase();
+
+    if accept.starts_with('.') {
+        filename.to_lowercase().ends_with(&accept)
+    } else if let Some(kind) = accept.strip_suffix("/*") {
+        kind == "*" || mime.split('/').next() == Some(kind)
+    } else {
+        accept == mime
+    }
+}
+
+/// Escapes field names and file names in multipart headers.
+///
+/// See: https://html.spec.whatwg.org/multipage/form-control-infrastructure.html#multipart-form-data
+fn escape(value: &str) -> String {
+    value.replace('\n', "%0A").replace('\r', "%0D").replace('"', "%22")
+}
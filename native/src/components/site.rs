@@ -1,9 +1,11 @@
 use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 use std::process::Child;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use anyhow::{Context, Result};
 use data_url::DataUrl;
-use log::info;
+use log::{info, warn};
 use reqwest::blocking::Client;
 use serde::{Deserialize, Serialize};
 use ulid::Ulid;
@@ -12,16 +14,30 @@ pub use web_app_manifest::WebAppManifest as SiteManifest;
 use web_app_manifest::resources::{IconResource, ProtocolHandlerResource};
 use web_app_manifest::types::{ImagePurpose, ImageSize, Url as ManifestUrl};
 
+use crate::components::manifest::{
+    ClientMode,
+    FileHandlerResource,
+    ManifestDiff,
+    ManifestExtras,
+    ShareTargetResource,
+};
+use crate::components::profile::Profile;
 use crate::components::runtime::Runtime;
 use crate::directories::ProjectDirs;
-use crate::storage::Config;
-use crate::utils::sanitize_string;
+use crate::storage::{Config, Instance, Policies, cached_fetch};
+use crate::utils::{construct_certificates_and_client, sanitize_string};
 
 const DOWNLOAD_ERROR: &str = "Failed to download web app manifest";
 const DATA_URL_ERROR: &str = "Failed to process web app manifest data URL";
 const PARSE_ERROR: &str = "Failed to parse web app manifest";
 const INVALID_URL: &str = "Web app without valid absolute URL is not possible";
 
+/// The current time in seconds since the Unix epoch.
+#[inline]
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
 /// Contains configuration for the web app.
 ///
 /// Most optional data here are just overwrites for information
@@ -36,6 +52,13 @@ pub struct SiteConfig {
     /// A custom web app name.
     pub name: Option<String>,
 
+    /// A unique web app alias.
+    ///
+    /// Can be used instead of the web app ID in console commands.
+    /// Aliases are compared case-insensitively and cannot be valid IDs.
+    #[serde(default)]
+    pub alias: Option<String>,
+
     /// A custom web app description.
     pub description: Option<String>,
 
@@ -51,6 +74,13 @@ pub struct SiteConfig {
     /// Direct URL of the site's web app manifest.
     pub manifest_url: Url,
 
+    /// URL of the page that the manifest was discovered from.
+    ///
+    /// Only set if the web app was installed from a page URL. Unlike
+    /// the document URL, this is the URL before following any redirects.
+    #[serde(default)]
+    pub page_url: Option<Url>,
+
     /// Custom web app categories.
     pub categories: Option<Vec<String>>,
 
@@ -75,6 +105,21 @@ pub struct SiteConfig {
     #[serde(default)]
     pub enabled_protocol_handlers: Vec<String>,
 
+    /// Enabled file handlers.
+    ///
+    /// Contains MIME types from the web app's file handlers that
+    /// are registered to the operating system. Files can only be
+    /// opened with the web app if their types are included here.
+    #[serde(default)]
+    pub enabled_file_handlers: Vec<String>,
+
+    /// A custom client mode.
+    ///
+    /// Determines how the web app is launched when it is already open.
+    /// Overwrites the client mode from the manifest launch handler.
+    #[serde(default)]
+    pub client_mode: Option<ClientMode>,
+
     /// Custom protocol handlers.
     ///
     /// Contains protocol handlers dynamically registered using
@@ -91,6 +136,84 @@ pub struct SiteConfig {
     /// Whether the web app should be launched on the browser launch.
     #[serde(default)]
     pub launch_on_browser: bool,
+
+    /// Arguments to be passed to the Firefox runtime for this web app.
+    ///
+    /// Passed after the global arguments.
+    #[serde(default)]
+    pub arguments: Vec<String>,
+
+    /// Environment variables to be passed to the Firefox runtime for this web app.
+    ///
+    /// Take precedence over the global variables with the same name.
+    #[serde(default)]
+    pub variables: BTreeMap<String, String>,
+
+    /// A HTTP client configuration used when updating the web app.
+    ///
+    /// Allows updating web apps that require a custom user agent or root
+    /// certificates without specifying them on every update.
+    #[serde(default)]
+    pub client: Option<SiteClientConfig>,
+}
+
+/// A HTTP client configuration stored for the web app.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Default)]
+#[serde(default)]
+pub struct SiteClientConfig {
+    /// A custom user-agent header.
+    pub user_agent: Option<String>,
+
+    /// A list of paths to DER certificate files.
+    pub tls_root_certificates_der: Option<Vec<PathBuf>>,
+
+    /// A list of paths to PEM certificate files.
+    pub tls_root_certificates_pem: Option<Vec<PathBuf>>,
+
+    /// Whether the client accepts invalid certs (dangerous).
+    pub tls_danger_accept_invalid_certs: bool,
+
+    /// Whether the client accepts invalid hostnames (dangerous).
+    pub tls_danger_accept_invalid_hostnames: bool,
+}
+
+impl SiteClientConfig {
+    /// Constructs the HTTP client with this configuration.
+    #[inline]
+    pub fn construct(&self) -> Result<Client> {
+        construct_certificates_and_client(
+            self.user_agent.as_deref(),
+            &self.tls_root_certificates_der,
+            &self.tls_root_certificates_pem,
+            self.tls_danger_accept_invalid_certs,
+            self.tls_danger_accept_invalid_hostnames,
+        )
+    }
+}
+
+/// Inputs of a single web app launch.
+#[derive(Debug, Clone)]
+pub struct LaunchOptions<'a> {
+    /// URLs to open, or the start URL if empty.
+    pub urls: &'a [Url],
+
+    /// Files passed to the launch queue of the URL at the same index.
+    pub files: &'a [Vec<PathBuf>],
+
+    /// A share target request that is sent when launching the URL.
+    pub share: Option<&'a Path>,
+
+    /// Global arguments to be passed to the runtime.
+    pub arguments: &'a [String],
+
+    /// Global environment variables to be passed to the runtime.
+    pub variables: &'a BTreeMap<String, String>,
+
+    /// System-wide policies, which can lock the arguments and variables.
+    pub policies: &'a Policies,
+
+    /// The running instance that receives the launch, if any.
+    pub instance: Option<&'a Instance>,
 }
 
 #[non_exhaustive]
@@ -112,92 +235,194 @@ pub struct Site {
 
     /// A web app manifest.
     pub manifest: SiteManifest,
+
+    /// Manifest members that are not supported by the manifest parser.
+    ///
+    /// Not set for web apps installed before these members were supported,
+    /// so their manifest is parsed again on the next update even if it did
+    /// not change.
+    #[serde(default)]
+    pub extras: Option<ManifestExtras>,
+
+    /// HTTP cache validators of the downloaded manifest and icons.
+    ///
+    /// Maps URLs to their `ETag` or `Last-Modified` values from the time
+    /// when they were last used by this web app. Used to skip web apps
+    /// whose manifest and icons did not change when updating them.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub validators: BTreeMap<String, String>,
+
+    /// A time when the web app was last updated, in seconds since the Unix epoch.
+    ///
+    /// Used to skip recently updated web apps when updating all web apps.
+    /// Not set for web apps that were not updated since this was stored.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_updated: Option<u64>,
 }
 
 impl Site {
-    fn download(url: &Url, client: &Client) -> Result<String> {
-        // If the URL is not a data URL, just download it using reqwest
-        let json = if url.scheme() != "data" {
-            client
-                .get(url.to_owned())
-                .header(reqwest::header::REFERER, url.to_string())
-                .send()?
-                .text()?
+    /// Downloads the manifest and returns it with its HTTP cache validator.
+    fn download(url: &Url, client: &Client) -> Result<(String, Option<String>)> {
+        // If the URL is not a data URL, just download it using reqwest and the HTTP cache
+        let (body, validator) = if url.scheme() != "data" {
+            let request =
+                client.get(url.to_owned()).header(reqwest::header::REFERER, url.to_string());
+            let response = cached_fetch(client, request.build()?)?;
+            (response.body, response.validator)
 
         // If the URL is a data URL (used for installing non-PWA sites), decode it using data-url
         } else {
             let url = DataUrl::process(url.as_str()).context(DATA_URL_ERROR)?;
             let (body, _) = url.decode_to_vec().context(DATA_URL_ERROR)?;
-            String::from_utf8(body).context(DATA_URL_ERROR)?
+            (body, None)
         };
 
         // Trim BOM from the URL to prevent JSON parse errors
-        Ok(json.trim_start_matches('\u{feff}').into())
+        let json = String::from_utf8_lossy(&body);
+        Ok((json.trim_start_matches('\u{feff}').into(), validator))
     }
 
-    #[inline]
-    pub fn new(profile: Ulid, config: SiteConfig, client: &Client) -> Result<Self> {
+    /// Downloads and processes the manifest the same way as when installing a web app.
+    ///
+    /// Returns the processed manifest, its extra members and its HTTP cache validator, if any.
+    pub fn fetch(
+        manifest_url: &Url,
+        document_url: &Url,
+        client: &Client,
+    ) -> Result<(SiteManifest, ManifestExtras, Option<String>)> {
         info!("Downloading the web app manifest");
-        let json = Self::download(&config.manifest_url, client).context(DOWNLOAD_ERROR)?;
+        let (json, validator) = Self::download(manifest_url, client).context(DOWNLOAD_ERROR)?;
 
         // If the manifest URL is a data URL, replace it with the document URL
-        let manifest_url = if config.manifest_url.scheme() != "data" {
-            &config.manifest_url
-        } else {
-            &config.document_url
-        };
+        let base_url = if manifest_url.scheme() != "data" { manifest_url } else { document_url };
 
         info!("Parsing the web app manifest");
         let mut manifest: SiteManifest = serde_json::from_str(&json).context(PARSE_ERROR)?;
-        manifest.process(&config.document_url, manifest_url).context(PARSE_ERROR)?;
+        manifest.process(document_url, base_url).context(PARSE_ERROR)?;
+        let extras = ManifestExtras::parse(&json, &manifest, base_url);
 
-        Ok(Self { ulid: Ulid::new(), profile, config, manifest })
+        Ok((manifest, extras, validator))
     }
 
     #[inline]
-    pub fn update(&mut self, client: &Client) -> Result<()> {
+    pub fn new(profile: Ulid, config: SiteConfig, client: &Client) -> Result<Self> {
+        let (manifest, extras, validator) =
+            Self::fetch(&config.manifest_url, &config.document_url, client)?;
+
+        let mut validators = BTreeMap::new();
+        if let Some(validator) = validator {
+            validators.insert(config.manifest_url.to_string(), validator);
+        }
+
+        let extras = Some(extras);
+        let last_updated = Some(now());
+        Ok(Self { ulid: Ulid::new(), profile, config, manifest, extras, validators, last_updated })
+    }
+
+    /// Downloads the latest manifest and compares it with the current one.
+    ///
+    /// Changes that require approval are passed to the `approve` callback,
+    /// and the manifest is only replaced if it returns `true`. Returns the
+    /// differences between the manifests and whether they were applied.
+    pub fn update<F>(&mut self, client: &Client, approve: F) -> Result<ManifestDiff>
+    where
+        F: FnOnce(&ManifestDiff) -> Result<bool>,
+    {
         // There is nothing to update if the manifest is a data URL because it is always static
         if self.config.manifest_url.scheme() == "data" {
-            return Ok(());
+            return Ok(ManifestDiff::default());
         }
 
         info!("Downloading the web app manifest");
-        let json = Self::download(&self.config.manifest_url, client).context(DOWNLOAD_ERROR)?;
+        let manifest_url = self.config.manifest_url.to_string();
+        let (json, validator) =
+            Self::download(&self.config.manifest_url, client).context(DOWNLOAD_ERROR)?;
+
+        // There is nothing to parse and compare if the manifest did not change
+        let unchanged =
+            validator.is_some() && validator.as_ref() == self.validators.get(&manifest_url);
+        if unchanged && self.extras.is_some() {
+            info!("The web app manifest has not changed");
+            return Ok(ManifestDiff::default());
+        }
 
         info!("Parsing the web app manifest");
         let mut manifest: SiteManifest = serde_json::from_str(&json).context(PARSE_ERROR)?;
         manifest
             .process(&self.config.document_url, &self.config.manifest_url)
             .context(PARSE_ERROR)?;
+        let extras = ManifestExtras::parse(&json, &manifest, &self.config.manifest_url);
+
+        // Web apps installed before extras were stored cannot know their old extras,
+        // so the new ones are only stored as a baseline without reporting them as changes
+        let mut diff = ManifestDiff::new(&self.manifest, &manifest);
+        if let Some(old) = &self.extras {
+            diff = diff.with_extras(old, &extras);
+        }
+
+        // Members that are not compared, such as colors and categories, are still used by
+        // system integration, so they also count as a modification, but not as a change
+        let members = serde_json::to_value(&self.manifest)? != serde_json::to_value(&manifest)?;
+        diff.modified = !diff.is_empty() || members || self.extras.as_ref() != Some(&extras);
+
+        if diff.requires_approval() && !approve(&diff)? {
+            return Ok(diff);
+        }
+
+        // Rejected manifests are not recorded, so they are compared again on the next update
+        match validator {
+            Some(validator) => self.validators.insert(manifest_url, validator),
+            None => self.validators.remove(&manifest_url),
+        };
 
         self.manifest = manifest;
-        Ok(())
+        self.extras = Some(extras);
+        diff.applied = true;
+        Ok(diff)
     }
 
     #[inline]
-    pub fn launch<I: IntoIterator<Item = (String, String)>>(
+    pub fn launch(
         &self,
         dirs: &ProjectDirs,
         runtime: &Runtime,
         config: &Config,
-        urls: &[Url],
-        arguments: &[String],
-        variables: I,
+        profile: &Profile,
+        options: &LaunchOptions,
     ) -> Result<Child> {
-        let profile = dirs.userdata.join("profiles").join(self.profile.to_string());
+        let directory = dirs.userdata.join("profiles").join(self.profile.to_string());
+
+        // Use the remoting name of the running instance, so Firefox hands the launch to it
+        // All web apps in the same profile share it, because they run in the same process
+        let name = match options.instance {
+            Some(instance) => instance.class.clone(),
+            None => format!("FFPWA-{}", self.ulid.to_string()),
+        };
 
         // Pass all required web app arguments to the runtime
         #[rustfmt::skip]
         let mut args = vec![
             "--class".into(), format!("FFPWA-{}", self.ulid.to_string()),
-            "--name".into(), format!("FFPWA-{}", self.ulid.to_string()),
-            "--profile".into(), profile.display().to_string(),
+            "--name".into(), name,
+            "--profile".into(), directory.display().to_string(),
             "--pwa".into(), self.ulid.to_string(),
         ];
 
         // Allow launching web app on specific URLs
-        for url in urls {
+        // Files follow the URL whose launch queue they are passed to
+        for (index, url) in options.urls.iter().enumerate() {
             args.extend_from_slice(&["--url".into(), url.to_string()]);
+            for file in options.files.get(index).into_iter().flatten() {
+                args.extend_from_slice(&["--launch-file".into(), file.display().to_string()]);
+            }
+        }
+
+        // Pass how the launch is handled when the web app is already open
+        args.extend_from_slice(&["--client-mode".into(), self.client_mode().to_string()]);
+
+        // Pass the share target request that is sent when launching the URL
+        if let Some(share) = options.share {
+            args.extend_from_slice(&["--share-request".into(), share.display().to_string()]);
         }
 
         // Pass variables needed for specific runtime features
@@ -213,8 +438,33 @@ impl Site {
         }
 
         // Include all user arguments and variables and launch the runtime
-        args.extend_from_slice(arguments);
-        vars.extend(variables);
+        // Global ones are applied first, then profile ones, and then web app ones
+        // Global ones that are locked by policies cannot be overridden
+        args.extend_from_slice(options.arguments);
+        vars.extend(options.variables.clone());
+
+        match options.policies.is_locked("arguments") {
+            false => {
+                args.extend_from_slice(&profile.arguments);
+                args.extend_from_slice(&self.config.arguments);
+            }
+            true if !profile.arguments.is_empty() || !self.config.arguments.is_empty() => {
+                warn!("Ignoring profile and web app arguments, as arguments are locked");
+            }
+            true => {}
+        }
+
+        match options.policies.is_locked("variables") {
+            false => {
+                vars.extend(profile.variables.clone());
+                vars.extend(self.config.variables.clone());
+            }
+            true if !profile.variables.is_empty() || !self.config.variables.is_empty() => {
+                warn!("Ignoring profile and web app variables, as variables are locked");
+            }
+            true => {}
+        }
+
         runtime.run(&args, vars)
     }
 }
@@ -286,6 +536,70 @@ impl Site {
         }
     }
 
+    /// Checks whether any web app or shortcut icon changed since this web app used it.
+    ///
+    /// Icons are checked with conditional requests through the HTTP cache, so
+    /// unchanged icons are not downloaded again. Icons without a validator or
+    /// that cannot be checked are considered changed. Returns whether any icon
+    /// changed and the current validators that should be recorded with
+    /// [`Site::record_icon_validators`] once the icons are stored.
+    pub fn check_icons(&self, client: &Client) -> (bool, BTreeMap<String, String>) {
+        let shortcuts = self.manifest.shortcuts.iter().flat_map(|shortcut| &shortcut.icons);
+        let mut validators = BTreeMap::new();
+        let mut modified = false;
+
+        for icon in self.icons().iter().chain(shortcuts) {
+            let ManifestUrl::Absolute(url) = &icon.src else { continue };
+            if url.scheme() == "data" || validators.contains_key(url.as_str()) {
+                continue;
+            }
+
+            let request = client.get(url.to_owned()).build();
+            let validator = match request.map(|request| cached_fetch(client, request)) {
+                Ok(Ok(response)) => response.validator,
+                _ => None,
+            };
+
+            match validator {
+                Some(validator) => {
+                    modified |= self.validators.get(url.as_str()) != Some(&validator);
+                    validators.insert(url.to_string(), validator);
+                }
+                None => modified = true,
+            }
+        }
+
+        // Icons that are no longer used also count as a change
+        let manifest_url = self.config.manifest_url.as_str();
+        modified |=
+            self.validators.keys().any(|url| url != manifest_url && !validators.contains_key(url));
+
+        (modified, validators)
+    }
+
+    /// Records that the web app was updated now.
+    #[inline]
+    pub fn mark_updated(&mut self) {
+        self.last_updated = Some(now());
+    }
+
+    /// Whether the web app was not updated for the duration.
+    ///
+    /// Web apps without a recorded update time are always considered old.
+    pub fn is_older_than(&self, age: Duration) -> bool {
+        match self.last_updated {
+            Some(updated) => now().saturating_sub(updated) >= age.as_secs(),
+            None => true,
+        }
+    }
+
+    /// Replaces validators of icons with the ones returned by [`Site::check_icons`].
+    pub fn record_icon_validators(&mut self, validators: BTreeMap<String, String>) {
+        let manifest_url = self.config.manifest_url.to_string();
+        self.validators.retain(|url, _| *url == manifest_url);
+        self.validators.extend(validators);
+    }
+
     /// Categories can be used for user organization.
     ///
     /// There is no fixed list of categories, but some known categories are converted
@@ -317,4 +631,64 @@ impl Site {
         .map(|item| sanitize_string(item))
         .collect()
     }
+
+    /// Unique identity of the web app from the manifest.
+    ///
+    /// Web apps installed before the identity was stored use
+    /// the manifest start URL, which is the default identity.
+    pub fn identity(&self) -> Url {
+        if let Some(id) = self.extras.as_ref().and_then(|extras| extras.id.as_ref()) {
+            return id.clone();
+        }
+
+        let ManifestUrl::Absolute(url) = &self.manifest.start_url else {
+            unreachable!("{}", INVALID_URL)
+        };
+
+        let mut url = url.clone();
+        url.set_fragment(None);
+        url
+    }
+
+    /// File handlers from the manifest.
+    #[inline]
+    pub fn file_handlers(&self) -> &[FileHandlerResource] {
+        self.extras.as_ref().map_or(&[], |extras| &extras.file_handlers)
+    }
+
+    /// Share target from the manifest.
+    #[inline]
+    pub fn share_target(&self) -> Option<&ShareTargetResource> {
+        self.extras.as_ref().and_then(|extras| extras.share_target.as_ref())
+    }
+
+    /// First tries the user-specified client mode, then tries the manifest launch handler.
+    /// If no client mode is specified, the launch is handled according to the runtime settings.
+    pub fn client_mode(&self) -> ClientMode {
+        let handler = self.extras.as_ref().and_then(|extras| extras.launch_handler.as_ref());
+        self.config.client_mode.or(handler.map(|handler| handler.client_mode)).unwrap_or_default()
+    }
+
+    /// File types that can be opened with the web app.
+    ///
+    /// Maps the enabled MIME types to their file extensions
+    /// from all file handlers that accept them.
+    pub fn file_types(&self) -> BTreeMap<String, Vec<String>> {
+        let mut types = BTreeMap::<String, Vec<String>>::new();
+
+        for handler in self.file_handlers() {
+            for (mime, extensions) in &handler.accept {
+                if self.config.enabled_file_handlers.contains(mime) {
+                    types.entry(mime.clone()).or_default().extend(extensions.iter().cloned());
+                }
+            }
+        }
+
+        for extensions in types.values_mut() {
+            extensions.sort_unstable();
+            extensions.dedup();
+        }
+
+        types
+    }
 }
@@ -11,6 +11,7 @@ use crate::connector::process::Process;
 use crate::connector::request::ConnectorRequest;
 use crate::connector::response::ConnectorResponse;
 use crate::directories::ProjectDirs;
+use crate::storage::{JournalSource, set_journal_source};
 
 mod process;
 mod request;
@@ -25,6 +26,8 @@ pub struct Connection<'a> {
 impl<'a> Connection<'a> {
     pub fn start(dirs: &'a ProjectDirs, debugmode: bool) -> Result<()> {
         let connection = Self { dirs, debugmode };
+        set_journal_source(JournalSource::Connector);
+
         info!("Connection established: {:?}", env::args().collect::<Vec<String>>());
 
         // Wrapped into a closure to emulate currently unstable `try` blocks
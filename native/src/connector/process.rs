@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use anyhow::{Context, Result, bail};
 use cfg_if::cfg_if;
 use log::{info, warn};
@@ -5,18 +7,24 @@ use log::{info, warn};
 use crate::components::runtime::Runtime;
 use crate::connector::Connection;
 use crate::connector::request::{
+    CopySite,
     CreateProfile,
     GetConfig,
     GetProfileList,
+    GetRunningSites,
     GetSiteList,
+    GetStorageBackups,
     GetSystemVersions,
     InstallRuntime,
     InstallSite,
     LaunchSite,
+    MoveSite,
     PatchAllProfiles,
     RegisterProtocolHandler,
     RemoveProfile,
+    RestoreStorageBackup,
     SetConfig,
+    StopSite,
     UninstallRuntime,
     UninstallSite,
     UnregisterProtocolHandler,
@@ -25,22 +33,25 @@ use crate::connector::request::{
     UpdateSite,
 };
 use crate::connector::response::ConnectorResponse;
-use crate::console::Run;
 use crate::console::app::{
     ProfileCreateCommand,
     ProfileRemoveCommand,
     ProfileUpdateCommand,
     RuntimeInstallCommand,
     RuntimeUninstallCommand,
+    SiteCopyCommand,
     SiteInstallCommand,
+    SiteKillCommand,
     SiteLaunchCommand,
+    SiteMoveCommand,
     SiteUninstallCommand,
     SiteUpdateCommand,
+    StorageRestoreCommand,
 };
+use crate::console::{Run, format_assignments};
 use crate::integrations;
 use crate::integrations::IntegrationInstallArgs;
-use crate::storage::Storage;
-use crate::utils::construct_certificates_and_client;
+use crate::storage::{InstanceRegistry, Storage};
 
 pub trait Process {
     fn process(&self, connection: &Connection) -> Result<ConnectorResponse>;
@@ -67,14 +78,17 @@ impl Process for GetSystemVersions {
 
 impl Process for GetConfig {
     fn process(&self, connection: &Connection) -> Result<ConnectorResponse> {
-        let storage = Storage::load(connection.dirs)?;
-        Ok(ConnectorResponse::Config(storage.config))
+        let storage = Storage::load_index(connection.dirs)?;
+        let locked = storage.policies.locked;
+        Ok(ConnectorResponse::Config { config: storage.config, locked })
     }
 }
 
 impl Process for SetConfig {
     fn process(&self, connection: &Connection) -> Result<ConnectorResponse> {
-        let mut storage = Storage::load(connection.dirs)?;
+        let _lock = Storage::lock(connection.dirs)?;
+        let mut storage = Storage::load_index(connection.dirs)?;
+        storage.policies.check_config(&storage.config, &self.0)?;
         self.0.clone_into(&mut storage.config);
         storage.write(connection.dirs)?;
         Ok(ConnectorResponse::ConfigSet)
@@ -115,9 +129,11 @@ impl Process for GetSiteList {
 impl Process for LaunchSite {
     fn process(&self, _connection: &Connection) -> Result<ConnectorResponse> {
         let command = SiteLaunchCommand {
-            id: self.id,
+            id: self.id.into(),
             url: self.url.to_owned(),
             protocol: None,
+            file: vec![],
+            share: None,
             arguments: vec![],
             #[cfg(platform_macos)]
             direct_launch: false,
@@ -137,13 +153,18 @@ impl Process for InstallSite {
             icon_url: self.icon_url.to_owned(),
             profile: self.profile.to_owned(),
             name: self.name.to_owned(),
+            alias: self.alias.to_owned(),
             description: self.description.to_owned(),
             categories: self.categories.to_owned(),
             keywords: self.keywords.to_owned(),
             launch_on_login: Some(self.launch_on_login),
             launch_on_browser: Some(self.launch_on_browser),
+            arguments: self.arguments.to_owned(),
+            variables: self.variables.as_ref().map(format_assignments),
+            if_installed: self.if_installed.into(),
             launch_now: self.launch_now,
             system_integration: true,
+            store_client_config: self.store_client_config,
             client: self.client.to_owned().into(),
         };
         let ulid = command._run()?;
@@ -154,7 +175,8 @@ impl Process for InstallSite {
 
 impl Process for UninstallSite {
     fn process(&self, _connection: &Connection) -> Result<ConnectorResponse> {
-        let command = SiteUninstallCommand { id: self.id, quiet: true, system_integration: true };
+        let command =
+            SiteUninstallCommand { id: self.id.into(), quiet: true, system_integration: true };
         command.run()?;
 
         Ok(ConnectorResponse::SiteUninstalled)
@@ -165,62 +187,119 @@ impl Process for UpdateSite {
     fn process(&self, _connection: &Connection) -> Result<ConnectorResponse> {
         // `categories` and `keywords` need some weird hack to be compatible with Clap
         // See [`crate::console::store_value_vec`] for more details
+        // Similarly, `variables` need to be converted into the `NAME=VALUE` format
         let command = SiteUpdateCommand {
-            id: self.id,
+            id: Some(self.id.into()),
+            all: false,
+            if_older_than: None,
+            #[cfg(platform_linux)]
+            schedule: None,
             start_url: self.start_url.to_owned(),
             icon_url: self.icon_url.to_owned(),
             name: self.name.to_owned(),
+            alias: self.alias.to_owned(),
             description: self.description.to_owned(),
             categories: self.categories.clone().map(|x| x.unwrap_or_else(|| vec!["".into()])),
             keywords: self.keywords.clone().map(|x| x.unwrap_or_else(|| vec!["".into()])),
             enabled_url_handlers: self.enabled_url_handlers.to_owned(),
             enabled_protocol_handlers: self.enabled_protocol_handlers.to_owned(),
+            enabled_file_handlers: self.enabled_file_handlers.to_owned(),
+            client_mode: self.client_mode.map(|mode| mode.map(|mode| mode.to_string())),
             launch_on_login: self.launch_on_login,
             launch_on_browser: self.launch_on_browser,
+            arguments: self.arguments.to_owned(),
+            variables: self.variables.as_ref().map(format_assignments),
             update_manifest: self.update_manifest,
             update_icons: self.update_icons,
             system_integration: true,
+            accept_manifest_changes: self.accept_manifest_changes,
+            quiet: true,
+            store_client_config: self.store_client_config,
             client: self.client.to_owned().into(),
         };
-        command.run()?;
+        let diff = command._run()?;
 
-        Ok(ConnectorResponse::SiteUpdated)
+        Ok(ConnectorResponse::SiteUpdated(diff))
     }
 }
 
 impl Process for UpdateAllSites {
+    fn process(&self, _connection: &Connection) -> Result<ConnectorResponse> {
+        let command = SiteUpdateCommand {
+            id: None,
+            all: true,
+            if_older_than: self.if_older_than.map(Duration::from_secs),
+            #[cfg(platform_linux)]
+            schedule: None,
+            start_url: None,
+            icon_url: None,
+            name: None,
+            alias: None,
+            description: None,
+            categories: None,
+            keywords: None,
+            enabled_url_handlers: None,
+            enabled_protocol_handlers: None,
+            enabled_file_handlers: None,
+            client_mode: None,
+            launch_on_login: None,
+            launch_on_browser: None,
+            arguments: None,
+            variables: None,
+            update_manifest: self.update_manifest,
+            update_icons: self.update_icons,
+            system_integration: true,
+            accept_manifest_changes: self.accept_manifest_changes,
+            quiet: true,
+            store_client_config: false,
+            client: self.client.to_owned().into(),
+        };
+        let diffs = command._run_all()?;
+
+        Ok(ConnectorResponse::AllSitesUpdated(diffs))
+    }
+}
+
+impl Process for GetRunningSites {
     fn process(&self, connection: &Connection) -> Result<ConnectorResponse> {
-        let mut storage = Storage::load(connection.dirs)?;
-
-        for site in storage.sites.values_mut() {
-            info!("Updating web app {}", site.ulid);
-            let old_name = site.name();
-
-            let client = construct_certificates_and_client(
-                self.client.user_agent.as_deref(),
-                &self.client.tls_root_certificates_der,
-                &self.client.tls_root_certificates_pem,
-                self.client.tls_danger_accept_invalid_certs,
-                self.client.tls_danger_accept_invalid_hostnames,
-            )?;
-
-            if self.update_manifest {
-                site.update(&client).context("Failed to update web app manifest")?;
-            }
+        let instances = InstanceRegistry::new(connection.dirs).list();
+        Ok(ConnectorResponse::RunningSiteList(instances))
+    }
+}
 
-            integrations::install(&IntegrationInstallArgs {
-                site,
-                dirs: connection.dirs,
-                client: Some(&client),
-                update_manifest: self.update_manifest,
-                update_icons: self.update_icons,
-                old_name: Some(&old_name),
-            })
-            .context("Failed to update system integration")?;
-        }
+impl Process for MoveSite {
+    fn process(&self, _connection: &Connection) -> Result<ConnectorResponse> {
+        let command = SiteMoveCommand {
+            id: self.id.into(),
+            profile: self.profile,
+            migrate_data: self.migrate_data,
+        };
+        command.run()?;
 
-        storage.write(connection.dirs)?;
-        Ok(ConnectorResponse::AllSitesUpdated)
+        Ok(ConnectorResponse::SiteMoved)
+    }
+}
+
+impl Process for CopySite {
+    fn process(&self, _connection: &Connection) -> Result<ConnectorResponse> {
+        let command = SiteCopyCommand {
+            id: self.id.into(),
+            profile: self.profile,
+            system_integration: true,
+            client: self.client.to_owned().into(),
+        };
+        let ulid = command._run()?;
+
+        Ok(ConnectorResponse::SiteCopied(ulid))
+    }
+}
+
+impl Process for StopSite {
+    fn process(&self, _connection: &Connection) -> Result<ConnectorResponse> {
+        let command = SiteKillCommand { id: self.id.into(), force: self.force };
+        let closed = command._run()?;
+
+        Ok(ConnectorResponse::SiteStopped(closed))
     }
 }
 
@@ -237,6 +316,9 @@ impl Process for CreateProfile {
             name: self.name.to_owned(),
             description: self.description.to_owned(),
             template: self.template.to_owned(),
+            arguments: self.arguments.to_owned(),
+            variables: self.variables.as_ref().map(format_assignments),
+            prefs: self.prefs.as_ref().map(format_assignments),
         };
         let ulid = command._run()?;
 
@@ -260,6 +342,9 @@ impl Process for UpdateProfile {
             name: self.name.to_owned(),
             description: self.description.to_owned(),
             template: self.template.to_owned(),
+            arguments: self.arguments.to_owned(),
+            variables: self.variables.as_ref().map(format_assignments),
+            prefs: self.prefs.as_ref().map(format_assignments),
         };
         command.run()?;
 
@@ -293,7 +378,8 @@ impl Process for PatchAllProfiles {
 
 impl Process for RegisterProtocolHandler {
     fn process(&self, connection: &Connection) -> Result<ConnectorResponse> {
-        let mut storage = Storage::load(connection.dirs)?;
+        let _lock = Storage::lock(connection.dirs)?;
+        let mut storage = Storage::load_site(connection.dirs, self.site)?;
         let site = storage.sites.get_mut(&self.site).context("Web app does not exist")?;
 
         // Check if this protocol scheme is already used in custom or manifest handlers
@@ -329,7 +415,8 @@ impl Process for RegisterProtocolHandler {
 
 impl Process for UnregisterProtocolHandler {
     fn process(&self, connection: &Connection) -> Result<ConnectorResponse> {
-        let mut storage = Storage::load(connection.dirs)?;
+        let _lock = Storage::lock(connection.dirs)?;
+        let mut storage = Storage::load_site(connection.dirs, self.site)?;
         let site = storage.sites.get_mut(&self.site).context("Web app does not exist")?;
 
         // Remove handler from both lists
@@ -351,3 +438,19 @@ impl Process for UnregisterProtocolHandler {
         Ok(ConnectorResponse::ProtocolHandlerUnregistered)
     }
 }
+
+impl Process for GetStorageBackups {
+    fn process(&self, connection: &Connection) -> Result<ConnectorResponse> {
+        let backups = Storage::backups(connection.dirs)?;
+        Ok(ConnectorResponse::StorageBackupList(backups))
+    }
+}
+
+impl Process for RestoreStorageBackup {
+    fn process(&self, _connection: &Connection) -> Result<ConnectorResponse> {
+        let command = StorageRestoreCommand { backup: self.backup.to_owned(), quiet: true };
+        command.run()?;
+
+        Ok(ConnectorResponse::StorageBackupRestored)
+    }
+}
@@ -1,12 +1,15 @@
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
 use anyhow::Result;
 use serde::{Deserialize, Deserializer};
+use serde_json::Value;
 use serde_with::{DefaultOnNull, OneOrMany};
 use ulid::Ulid;
 use url::Url;
 use web_app_manifest::resources::ProtocolHandlerResource;
 
+use crate::components::manifest::ClientMode;
 use crate::connector::response::ConnectorResponse;
 use crate::storage::Config;
 
@@ -114,13 +117,16 @@ pub struct GetSystemVersions;
 ///
 /// # Returns
 ///
-/// [`ConnectorResponse::Config`] - Config of the native program.
+/// [`ConnectorResponse::Config`] - Config of the native program
+/// and keys locked by the system policy.
 ///
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub struct GetConfig;
 
 /// Sets config of the native program.
 ///
+/// Fails if the config changes keys locked by the system policy.
+///
 /// # Parameters
 ///
 /// See [fields](#fields).
@@ -252,6 +258,11 @@ pub struct InstallSite {
     /// If not set, defaults to the value specified in the manifest.
     pub name: Option<String>,
 
+    /// A unique web app alias.
+    ///
+    /// Can be used instead of the web app ID in console commands.
+    pub alias: Option<String>,
+
     /// A custom web app description.
     ///
     /// Can be set by the user to overwrite the default description.
@@ -283,10 +294,27 @@ pub struct InstallSite {
     #[serde(default)]
     pub launch_on_browser: bool,
 
+    /// What to do when the same web app is already installed in the profile (default: `refuse`).
+    ///
+    /// Web apps are the same when their manifests have the same identity.
+    /// When updated, the ID of the already installed web app is returned.
+    #[serde(default)]
+    pub if_installed: IfInstalled,
+
     /// Whether the web app should be launch when the installation finishes.
     #[serde(default)]
     pub launch_now: bool,
 
+    /// Arguments to be passed to the Firefox runtime for this web app.
+    pub arguments: Option<Vec<String>>,
+
+    /// Environment variables to be passed to the Firefox runtime for this web app.
+    pub variables: Option<BTreeMap<String, String>>,
+
+    /// Whether the HTTP client configuration should be stored for later updates (default: `false`).
+    #[serde(default)]
+    pub store_client_config: bool,
+
     /// Contains a HTTP client configuration.
     #[serde(default)]
     pub client: HTTPClientConfig,
@@ -320,7 +348,7 @@ pub struct UninstallSite {
 ///
 /// # Returns
 ///
-/// [`ConnectorResponse::SiteUpdated`] - No data.
+/// [`ConnectorResponse::SiteUpdated`] - Changes of the web app manifest.
 ///
 #[derive(Deserialize, Debug, Eq, PartialEq, Clone)]
 pub struct UpdateSite {
@@ -345,6 +373,10 @@ pub struct UpdateSite {
     #[serde(default, deserialize_with = "double_option")]
     pub name: Option<Option<String>>,
 
+    /// A unique web app alias.
+    #[serde(default, deserialize_with = "double_option")]
+    pub alias: Option<Option<String>>,
+
     /// A custom web app description.
     ///
     /// Can be set by the user to overwrite the default description.
@@ -376,6 +408,19 @@ pub struct UpdateSite {
     /// If empty, no handlers are registered to the operating system.
     pub enabled_protocol_handlers: Option<Vec<String>>,
 
+    /// Enabled file handlers.
+    ///
+    /// A list of enabled MIME types from file handlers of this web app.
+    /// If empty, no file types are registered to the operating system.
+    pub enabled_file_handlers: Option<Vec<String>>,
+
+    /// A custom client mode.
+    ///
+    /// Determines how the web app is launched when it is already open.
+    /// If unset, the client mode from the manifest launch handler is used.
+    #[serde(default, deserialize_with = "double_option")]
+    pub client_mode: Option<Option<ClientMode>>,
+
     /// Whether the web app should be launched on the system login (default: `false`).
     #[serde(default)]
     pub launch_on_login: Option<bool>,
@@ -384,6 +429,18 @@ pub struct UpdateSite {
     #[serde(default)]
     pub launch_on_browser: Option<bool>,
 
+    /// Arguments to be passed to the Firefox runtime for this web app.
+    ///
+    /// Passed after the global arguments.
+    /// If empty, only the global arguments are used.
+    pub arguments: Option<Vec<String>>,
+
+    /// Environment variables to be passed to the Firefox runtime for this web app.
+    ///
+    /// Take precedence over the global variables with the same name.
+    /// If empty, only the global variables are used.
+    pub variables: Option<BTreeMap<String, String>>,
+
     /// Whether the manifest should be updated (default: `true`).
     #[serde(default = "default_as_true")]
     pub update_manifest: bool,
@@ -392,20 +449,42 @@ pub struct UpdateSite {
     #[serde(default = "default_as_true")]
     pub update_icons: bool,
 
+    /// Whether manifest changes that require approval should be accepted (default: `false`).
+    ///
+    /// Changes of the identity, the scope, the start URL origin, and protocol and
+    /// file handlers are only applied when this is set. Otherwise, the current manifest
+    /// is kept and the changes are returned for review.
+    #[serde(default)]
+    pub accept_manifest_changes: bool,
+
+    /// Whether the HTTP client configuration should be stored for later updates (default: `false`).
+    ///
+    /// Removes the stored configuration when the client configuration is empty.
+    #[serde(default)]
+    pub store_client_config: bool,
+
     /// Contains a HTTP client configuration.
+    ///
+    /// Takes precedence over the configuration stored for the web app, unless empty.
     #[serde(default)]
     pub client: HTTPClientConfig,
 }
 
 /// Updates all web apps.
 ///
+/// Manifests and icons are checked with conditional HTTP requests, and
+/// system integration is only updated for web apps whose manifest or
+/// icons changed since the last update.
+/// Web apps that fail to update are skipped, and an error listing
+/// them is returned after all other web apps are updated.
+///
 /// # Parameters
 ///
 /// See [fields](#fields).
 ///
 /// # Returns
 ///
-/// [`ConnectorResponse::AllSitesUpdated`] - No data.
+/// [`ConnectorResponse::AllSitesUpdated`] - Changes of manifests of updated web apps.
 ///
 #[derive(Deserialize, Debug, Eq, PartialEq, Clone)]
 pub struct UpdateAllSites {
@@ -417,11 +496,125 @@ pub struct UpdateAllSites {
     #[serde(default = "default_as_true")]
     pub update_icons: bool,
 
+    /// Whether manifest changes that require approval should be accepted (default: `false`).
+    ///
+    /// Changes of the identity, the scope, the start URL origin, and protocol and
+    /// file handlers are only applied when this is set. Otherwise, the current manifest
+    /// is kept and the changes are returned for review.
+    #[serde(default)]
+    pub accept_manifest_changes: bool,
+
+    /// Only update web apps that were not updated for this number of seconds.
+    ///
+    /// Web apps that were never updated since the update time was stored are always updated.
+    pub if_older_than: Option<u64>,
+
+    /// Contains a HTTP client configuration.
+    ///
+    /// Takes precedence over the configuration stored for each web app, unless empty.
+    #[serde(default)]
+    pub client: HTTPClientConfig,
+}
+
+/// Moves a web app to another profile.
+///
+/// The web app keeps its ID and settings. It cannot be moved while it is running.
+///
+/// # Parameters
+///
+/// See [fields](#fields).
+///
+/// # Returns
+///
+/// [`ConnectorResponse::SiteMoved`] - No data.
+///
+#[derive(Deserialize, Debug, Eq, PartialEq, Clone)]
+pub struct MoveSite {
+    /// A web app ID.
+    pub id: Ulid,
+
+    /// A profile ID where the web app will be moved.
+    pub profile: Ulid,
+
+    /// Whether data of the web app origin should also be moved (default: `false`).
+    ///
+    /// Cookies and permissions are stored for the whole profile and are not moved.
+    /// Web apps in the old and new profile cannot be running while data are moved.
+    #[serde(default)]
+    pub migrate_data: bool,
+}
+
+/// Copies a web app to another profile.
+///
+/// The copy keeps all settings except the alias, and gets a new ID.
+///
+/// # Parameters
+///
+/// See [fields](#fields).
+///
+/// # Returns
+///
+/// [`ConnectorResponse::SiteCopied`] - Generated ID of the copied web app.
+///
+#[derive(Deserialize, Debug, Eq, PartialEq, Clone)]
+pub struct CopySite {
+    /// A web app ID.
+    pub id: Ulid,
+
+    /// A profile ID where the web app will be copied.
+    pub profile: Ulid,
+
     /// Contains a HTTP client configuration.
+    ///
+    /// Takes precedence over the configuration stored for the web app, unless empty.
     #[serde(default)]
     pub client: HTTPClientConfig,
 }
 
+/// Gets all running web apps.
+///
+/// Web apps are registered when they are launched, and web apps shown
+/// in an already running runtime share its process ID.
+///
+/// Web apps are only unregistered when their runtime process exits. A web app
+/// whose windows were closed while other web apps in the same runtime are still
+/// open is therefore still listed, and stopping it also stops the other web apps.
+///
+/// # Parameters
+///
+/// None.
+///
+/// # Returns
+///
+/// [`ConnectorResponse::RunningSiteList`] - Running web apps sorted from oldest to newest.
+///
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct GetRunningSites;
+
+/// Stops a running web app.
+///
+/// All web apps shown in the same runtime process are closed together.
+///
+/// # Parameters
+///
+/// See [fields](#fields).
+///
+/// # Returns
+///
+/// [`ConnectorResponse::SiteStopped`] - IDs of all closed web apps.
+///
+#[derive(Deserialize, Debug, Eq, PartialEq, Clone)]
+pub struct StopSite {
+    /// A web app ID.
+    pub id: Ulid,
+
+    /// Whether the runtime should be killed immediately (default: `false`).
+    ///
+    /// Otherwise, the runtime is asked to quit, except on Windows.
+    #[serde(default)]
+    pub force: bool,
+}
+
 /// Gets all available profiles.
 ///
 /// # Parameters
@@ -458,6 +651,17 @@ pub struct CreateProfile {
     /// All contents of the provided template directory
     /// will be copied to a newly-created profile.
     pub template: Option<PathBuf>,
+
+    /// Arguments to be passed to the Firefox runtime for web apps in this profile.
+    pub arguments: Option<Vec<String>>,
+
+    /// Environment variables to be passed to the Firefox runtime for web apps in this profile.
+    pub variables: Option<BTreeMap<String, String>>,
+
+    /// Firefox preferences of this profile.
+    ///
+    /// Values must be booleans, integers or strings.
+    pub prefs: Option<BTreeMap<String, Value>>,
 }
 
 /// Removes a profile.
@@ -508,6 +712,22 @@ pub struct UpdateProfile {
     /// All contents of the provided template directory
     /// will be copied to the currently-updated profile.
     pub template: Option<PathBuf>,
+
+    /// Arguments to be passed to the Firefox runtime for web apps in this profile.
+    ///
+    /// If empty, only the global and web app arguments are used.
+    pub arguments: Option<Vec<String>>,
+
+    /// Environment variables to be passed to the Firefox runtime for web apps in this profile.
+    ///
+    /// If empty, only the global and web app variables are used.
+    pub variables: Option<BTreeMap<String, String>>,
+
+    /// Firefox preferences of this profile.
+    ///
+    /// Values must be booleans, integers or strings.
+    /// If empty, no preferences are managed for this profile.
+    pub prefs: Option<BTreeMap<String, Value>>,
 }
 
 /// Patches all profiles and runtime.
@@ -579,6 +799,39 @@ pub struct UnregisterProtocolHandler {
     pub handler: ProtocolHandlerResource,
 }
 
+/// Gets all available storage backups.
+///
+/// Backups are created automatically every time the storage is saved.
+///
+/// # Parameters
+///
+/// None.
+///
+/// # Returns
+///
+/// [`ConnectorResponse::StorageBackupList`] - Backups sorted from newest to oldest.
+///
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct GetStorageBackups;
+
+/// Restores the storage from a backup.
+///
+/// The current storage is backed up before it is replaced.
+///
+/// # Parameters
+///
+/// See [fields](#fields).
+///
+/// # Returns
+///
+/// [`ConnectorResponse::StorageBackupRestored`] - No data.
+///
+#[derive(Deserialize, Debug, Eq, PartialEq, Clone)]
+pub struct RestoreStorageBackup {
+    /// A backup ID.
+    pub backup: String,
+}
+
 /// Contains a HTTP client configuration.
 #[derive(Deserialize, Debug, Eq, PartialEq, Clone, Default)]
 pub struct HTTPClientConfig {
@@ -613,10 +866,38 @@ impl Into<crate::console::app::HTTPClientConfig> for HTTPClientConfig {
     }
 }
 
+/// What to do when installing a web app that is already installed.
+#[derive(Deserialize, Debug, Eq, PartialEq, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum IfInstalled {
+    /// Fail without installing the web app again.
+    #[default]
+    Refuse,
+
+    /// Update the installed web app with the new settings instead.
+    Update,
+
+    /// Install the web app again as a separate web app.
+    Duplicate,
+}
+
+#[allow(clippy::from_over_into)]
+impl Into<crate::console::app::IfInstalled> for IfInstalled {
+    fn into(self) -> crate::console::app::IfInstalled {
+        match self {
+            Self::Refuse => crate::console::app::IfInstalled::Refuse,
+            Self::Update => crate::console::app::IfInstalled::Update,
+            Self::Duplicate => crate::console::app::IfInstalled::Duplicate,
+        }
+    }
+}
+
 deserialize_unit_struct!(GetSystemVersions);
 deserialize_unit_struct!(GetConfig);
 deserialize_unit_struct!(GetSiteList);
+deserialize_unit_struct!(GetRunningSites);
 deserialize_unit_struct!(GetProfileList);
+deserialize_unit_struct!(GetStorageBackups);
 
 build_request_enum!(
     GetSystemVersions,
@@ -630,6 +911,10 @@ build_request_enum!(
     UninstallSite,
     UpdateSite,
     UpdateAllSites,
+    MoveSite,
+    CopySite,
+    GetRunningSites,
+    StopSite,
     GetProfileList,
     CreateProfile,
     RemoveProfile,
@@ -637,4 +922,6 @@ build_request_enum!(
     PatchAllProfiles,
     RegisterProtocolHandler,
     UnregisterProtocolHandler,
+    GetStorageBackups,
+    RestoreStorageBackup,
 );
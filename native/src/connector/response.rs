@@ -1,11 +1,12 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
 use serde::Serialize;
 use ulid::Ulid;
 
+use crate::components::manifest::ManifestDiff;
 use crate::components::profile::Profile;
 use crate::components::site::Site;
-use crate::storage::Config;
+use crate::storage::{Config, Instance, StorageBackup};
 
 /// TODO: Docs
 #[derive(Serialize, Debug, PartialEq, Clone)]
@@ -33,7 +34,16 @@ pub enum ConnectorResponse {
     },
 
     /// Config of the native program.
-    Config(Config),
+    Config {
+        /// Config values.
+        #[serde(flatten)]
+        config: Config,
+
+        /// Config keys locked by the system policy.
+        ///
+        /// These keys cannot be changed with [`SetConfig`](crate::connector::request::SetConfig).
+        locked: BTreeSet<String>,
+    },
 
     /// Config of the native program has been set.
     ConfigSet,
@@ -57,10 +67,30 @@ pub enum ConnectorResponse {
     SiteUninstalled,
 
     /// Web app has been updated.
-    SiteUpdated,
+    ///
+    /// Contains changes of the web app manifest, and whether they were applied.
+    SiteUpdated(ManifestDiff),
 
     /// All web apps have been updated.
-    AllSitesUpdated,
+    ///
+    /// Contains changes of manifests of web apps whose manifests changed.
+    AllSitesUpdated(BTreeMap<Ulid, ManifestDiff>),
+
+    /// Web app has been moved.
+    SiteMoved,
+
+    /// Web app has been copied.
+    ///
+    /// Contains the generated ID of the copy.
+    SiteCopied(Ulid),
+
+    /// List of all running web apps.
+    RunningSiteList(Vec<Instance>),
+
+    /// Web app has been stopped.
+    ///
+    /// Contains IDs of all web apps that were closed with it.
+    SiteStopped(Vec<Ulid>),
 
     /// List of all available profiles.
     ProfileList(BTreeMap<Ulid, Profile>),
@@ -83,6 +113,12 @@ pub enum ConnectorResponse {
     /// Protocol handler has been unregistered.
     ProtocolHandlerUnregistered,
 
+    /// List of all available storage backups.
+    StorageBackupList(Vec<StorageBackup>),
+
+    /// Storage has been restored from a backup.
+    StorageBackupRestored,
+
     /// Something went wrong...
     Error(String),
 }
@@ -1,6 +1,10 @@
 #![allow(clippy::large_enum_variant)]
 
-use std::path::PathBuf;
+use std::convert::Infallible;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
 
 use clap::{ArgAction, Parser};
 use ulid::Ulid;
@@ -21,6 +25,19 @@ pub enum App {
     /// Manage the runtime
     #[clap(subcommand)]
     Runtime(RuntimeCommand),
+
+    /// Manage the storage
+    #[clap(subcommand)]
+    Storage(StorageCommand),
+
+    /// Check the storage and system integration for inconsistencies
+    Doctor(DoctorCommand),
+
+    /// Apply profiles and web apps described in a desired-state file
+    Apply(ApplyCommand),
+
+    /// Show the history of changes to web apps, profiles and settings
+    History(HistoryCommand),
 }
 
 #[derive(Parser, Debug, Eq, PartialEq, Clone)]
@@ -36,24 +53,56 @@ pub enum SiteCommand {
 
     /// Update a web app
     Update(SiteUpdateCommand),
+
+    /// Move a web app to another profile
+    Move(SiteMoveCommand),
+
+    /// Copy a web app to another profile
+    Copy(SiteCopyCommand),
+
+    /// Share data with a web app
+    Share(SiteShareCommand),
+
+    /// List running web apps
+    ///
+    /// Web apps are listed until their runtime process exits, so web apps whose windows
+    /// were closed while other web apps in the same runtime are still open are also listed.
+    Ps(SitePsCommand),
+
+    /// Stop a running web app
+    ///
+    /// All web apps in the same runtime process are stopped together.
+    Kill(SiteKillCommand),
+
+    /// Check a web app manifest for problems
+    Lint(SiteLintCommand),
 }
 
 #[derive(Parser, Debug, Eq, PartialEq, Clone)]
 pub struct SiteLaunchCommand {
-    /// Web app ID
-    pub id: Ulid,
+    /// Web app ID, alias, or unambiguous name or domain
+    pub id: SiteSelector,
 
     /// Arguments passed to the runtime
     pub arguments: Vec<String>,
 
     /// Launch web app on a custom start URL
-    #[clap(long, conflicts_with = "protocol", value_hint = clap::ValueHint::Url)]
+    #[clap(long, conflicts_with_all = ["protocol", "file"], value_hint = clap::ValueHint::Url)]
     pub url: Vec<Url>,
 
     /// Launch web app on a protocol handler URL
-    #[clap(long, conflicts_with = "url", value_hint = clap::ValueHint::Url)]
+    /// {n}Absolute file paths and file URLs are opened the same way as with `--file`
+    #[clap(long, conflicts_with_all = ["url", "file"], value_parser = parse_protocol_url, value_hint = clap::ValueHint::Url)]
     pub protocol: Option<Option<Url>>,
 
+    /// Launch web app with files passed to its file handlers
+    #[clap(long, num_args = 1.., conflicts_with_all = ["url", "protocol"], value_hint = clap::ValueHint::FilePath)]
+    pub file: Vec<PathBuf>,
+
+    /// Internal: Launch web app with a prepared share target request
+    #[clap(long, hide = true, requires = "url")]
+    pub share: Option<PathBuf>,
+
     /// Internal: Directly launch web app without system integration
     #[cfg(target_os = "macos")]
     #[clap(long, hide = true)]
@@ -62,12 +111,13 @@ pub struct SiteLaunchCommand {
 
 #[derive(Parser, Debug, Eq, PartialEq, Clone)]
 pub struct SiteInstallCommand {
-    /// Direct URL of the site's web app manifest
+    /// Direct URL of the site's web app manifest, or URL of a page that links to it
+    /// {n}Pages are only searched for the manifest when the document URL is not set
     #[clap(value_hint = clap::ValueHint::Url)]
     pub manifest_url: Url,
 
     /// Direct URL of the site's main document
-    /// {n}Defaults to the result of parsing a manifest URL with `.`
+    /// {n}Defaults to the page URL, or the result of parsing a manifest URL with `.`
     #[clap(long, value_hint = clap::ValueHint::Url)]
     pub document_url: Option<Url>,
 
@@ -88,6 +138,11 @@ pub struct SiteInstallCommand {
     #[clap(long)]
     pub name: Option<String>,
 
+    /// Set a unique web app alias
+    /// {n}Can be used instead of the web app ID in other commands
+    #[clap(long)]
+    pub alias: Option<String>,
+
     /// Set a custom web app description
     #[clap(long)]
     pub description: Option<String>,
@@ -108,6 +163,21 @@ pub struct SiteInstallCommand {
     #[clap(long)]
     pub launch_on_browser: Option<bool>,
 
+    /// Set arguments passed to the runtime for this web app
+    /// {n}Can be specified multiple times
+    #[clap(long, allow_hyphen_values = true)]
+    pub arguments: Option<Vec<String>>,
+
+    /// Set environment variables passed to the runtime for this web app
+    /// {n}Use the `NAME=VALUE` format
+    #[clap(long)]
+    pub variables: Option<Vec<String>>,
+
+    /// What to do when the same web app is already installed in the profile
+    /// {n}Web apps are the same when their manifests have the same identity
+    #[clap(long, value_enum, default_value_t = IfInstalled::Refuse)]
+    pub if_installed: IfInstalled,
+
     /// Launch this web app when the installation finishes
     #[clap(long)]
     pub launch_now: bool,
@@ -116,6 +186,10 @@ pub struct SiteInstallCommand {
     #[clap(long = "no-system-integration", action = ArgAction::SetFalse)]
     pub system_integration: bool,
 
+    /// Store the HTTP client configuration for later updates of this web app
+    #[clap(long)]
+    pub store_client_config: bool,
+
     /// Configuration of the HTTP client
     #[clap(flatten)]
     pub client: HTTPClientConfig,
@@ -123,8 +197,8 @@ pub struct SiteInstallCommand {
 
 #[derive(Parser, Debug, Eq, PartialEq, Clone)]
 pub struct SiteUninstallCommand {
-    /// Web app ID
-    pub id: Ulid,
+    /// Web app ID, alias, or unambiguous name or domain
+    pub id: SiteSelector,
 
     /// Disable any interactive prompts
     #[clap(short, long)]
@@ -137,8 +211,29 @@ pub struct SiteUninstallCommand {
 
 #[derive(Parser, Debug, Eq, PartialEq, Clone)]
 pub struct SiteUpdateCommand {
-    /// Web app ID
-    pub id: Ulid,
+    /// Web app ID, alias, or unambiguous name or domain
+    #[clap(required_unless_present = "all")]
+    pub id: Option<SiteSelector>,
+
+    /// Update all web apps
+    /// {n}Web apps that fail to update are skipped and reported at the end
+    #[clap(long, conflicts_with_all = [
+        "id", "start_url", "icon_url", "name", "alias", "description", "categories", "keywords",
+        "enabled_url_handlers", "enabled_protocol_handlers", "enabled_file_handlers", "client_mode",
+        "launch_on_login", "launch_on_browser", "arguments", "variables", "store_client_config",
+    ])]
+    pub all: bool,
+
+    /// Only update web apps that were not updated for the duration
+    /// {n}Use a number followed by `s`, `m`, `h`, `d` or `w`, such as `12h` or `7d`
+    #[clap(long, conflicts_with = "id", value_parser = parse_duration)]
+    pub if_older_than: Option<Duration>,
+
+    /// Periodically run this update in the background instead of updating now
+    /// {n}Uses a systemd user timer or an XDG autostart entry, or `none` to remove it
+    #[cfg(target_os = "linux")]
+    #[clap(long, requires = "all", value_enum)]
+    pub schedule: Option<UpdateSchedule>,
 
     /// Set a custom web app start URL
     #[clap(long, value_hint = clap::ValueHint::Url)]
@@ -152,6 +247,11 @@ pub struct SiteUpdateCommand {
     #[clap(long)]
     pub name: Option<Option<String>>,
 
+    /// Set a unique web app alias
+    /// {n}Can be used instead of the web app ID in other commands
+    #[clap(long)]
+    pub alias: Option<Option<String>>,
+
     /// Set a custom web app description
     #[clap(long)]
     pub description: Option<Option<String>>,
@@ -172,6 +272,16 @@ pub struct SiteUpdateCommand {
     #[clap(long)]
     pub enabled_protocol_handlers: Option<Vec<String>>,
 
+    /// Set enabled file handlers
+    /// {n}Contains MIME types from the web app file handlers
+    #[clap(long)]
+    pub enabled_file_handlers: Option<Vec<String>>,
+
+    /// Set how the web app is launched when it is already open
+    /// {n}Overwrites the manifest launch handler, or set to an empty value to use it again
+    #[clap(long, value_parser = ["auto", "navigate-new", "navigate-existing", "focus-existing"])]
+    pub client_mode: Option<Option<String>>,
+
     /// Set the web app to launch on the system login.
     #[clap(long)]
     pub launch_on_login: Option<bool>,
@@ -180,6 +290,16 @@ pub struct SiteUpdateCommand {
     #[clap(long)]
     pub launch_on_browser: Option<bool>,
 
+    /// Set arguments passed to the runtime for this web app
+    /// {n}Can be specified multiple times, or set to an empty value to clear them
+    #[clap(long, allow_hyphen_values = true)]
+    pub arguments: Option<Vec<String>>,
+
+    /// Set environment variables passed to the runtime for this web app
+    /// {n}Use the `NAME=VALUE` format, or set to an empty value to clear them
+    #[clap(long)]
+    pub variables: Option<Vec<String>>,
+
     /// Disable manifest updates
     #[clap(long = "no-manifest-updates", action = ArgAction::SetFalse)]
     pub update_manifest: bool,
@@ -192,6 +312,113 @@ pub struct SiteUpdateCommand {
     #[clap(long = "no-system-integration", action = ArgAction::SetFalse)]
     pub system_integration: bool,
 
+    /// Accept manifest changes that require approval without asking
+    /// {n}Includes changes of the identity, the scope, the start URL origin, and protocol and file handlers
+    #[clap(long)]
+    pub accept_manifest_changes: bool,
+
+    /// Disable any interactive prompts
+    /// {n}Manifest changes that require approval are rejected unless accepted
+    #[clap(short, long)]
+    pub quiet: bool,
+
+    /// Store the HTTP client configuration for later updates of this web app
+    /// {n}Removes the stored configuration when no HTTP client options are set
+    /// {n}HTTP client options always take precedence over the stored configuration
+    #[clap(long)]
+    pub store_client_config: bool,
+
+    /// Configuration of the HTTP client
+    #[clap(flatten)]
+    pub client: HTTPClientConfig,
+}
+
+#[derive(Parser, Debug, Eq, PartialEq, Clone)]
+pub struct SiteMoveCommand {
+    /// Web app ID, alias, or unambiguous name or domain
+    pub id: SiteSelector,
+
+    /// Profile where this web app will be moved
+    #[clap(long)]
+    pub profile: Ulid,
+
+    /// Also move data of the web app origin to the new profile
+    /// {n}Cookies and permissions are stored for the whole profile and are not moved
+    #[clap(long)]
+    pub migrate_data: bool,
+}
+
+#[derive(Parser, Debug, Eq, PartialEq, Clone)]
+pub struct SiteCopyCommand {
+    /// Web app ID, alias, or unambiguous name or domain
+    pub id: SiteSelector,
+
+    /// Profile where this web app will be copied
+    #[clap(long)]
+    pub profile: Ulid,
+
+    /// Disable system integration
+    #[clap(long = "no-system-integration", action = ArgAction::SetFalse)]
+    pub system_integration: bool,
+
+    /// Configuration of the HTTP client
+    #[clap(flatten)]
+    pub client: HTTPClientConfig,
+}
+
+#[derive(Parser, Debug, Eq, PartialEq, Clone)]
+pub struct SitePsCommand {}
+
+#[derive(Parser, Debug, Eq, PartialEq, Clone)]
+pub struct SiteShareCommand {
+    /// Web app ID, alias, or unambiguous name or domain
+    pub id: SiteSelector,
+
+    /// Share a title
+    #[clap(long)]
+    pub title: Option<String>,
+
+    /// Share a text
+    #[clap(long)]
+    pub text: Option<String>,
+
+    /// Share a URL
+    /// {n}Added to the text when the web app does not accept URLs
+    #[clap(long, value_hint = clap::ValueHint::Url)]
+    pub url: Option<Option<Url>>,
+
+    /// Share files
+    #[clap(long, num_args = 0.., value_hint = clap::ValueHint::FilePath)]
+    pub file: Vec<PathBuf>,
+}
+
+#[derive(Parser, Debug, Eq, PartialEq, Clone)]
+pub struct SiteKillCommand {
+    /// Web app ID, alias, or unambiguous name or domain
+    pub id: SiteSelector,
+
+    /// Kill the runtime immediately instead of asking it to quit
+    /// {n}The runtime is always killed immediately on Windows
+    #[clap(short, long)]
+    pub force: bool,
+}
+
+#[derive(Parser, Debug, Eq, PartialEq, Clone)]
+pub struct SiteLintCommand {
+    /// Direct URL of the site's web app manifest, or URL of a page that links to it
+    /// {n}Pages are only searched for the manifest when the document URL is not set
+    #[clap(value_hint = clap::ValueHint::Url)]
+    pub manifest_url: Url,
+
+    /// Direct URL of the site's main document
+    /// {n}Defaults to the page URL, or the result of parsing a manifest URL with `.`
+    #[clap(long, value_hint = clap::ValueHint::Url)]
+    pub document_url: Option<Url>,
+
+    /// Print found problems as JSON
+    #[clap(long)]
+    pub json: bool,
+
     /// Configuration of the HTTP client
     #[clap(flatten)]
     pub client: HTTPClientConfig,
@@ -230,6 +457,21 @@ pub struct ProfileCreateCommand {
     /// will be copied to a newly-created profile
     #[clap(long, value_hint = clap::ValueHint::DirPath)]
     pub template: Option<PathBuf>,
+
+    /// Set arguments passed to the runtime for web apps in this profile
+    /// {n}Can be specified multiple times
+    #[clap(long, allow_hyphen_values = true)]
+    pub arguments: Option<Vec<String>>,
+
+    /// Set environment variables passed to the runtime for web apps in this profile
+    /// {n}Use the `NAME=VALUE` format
+    #[clap(long)]
+    pub variables: Option<Vec<String>>,
+
+    /// Set Firefox preferences of this profile
+    /// {n}Use the `NAME=VALUE` format, where value is a boolean, integer or string
+    #[clap(long)]
+    pub prefs: Option<Vec<String>>,
 }
 
 #[derive(Parser, Debug, Eq, PartialEq, Clone)]
@@ -260,6 +502,21 @@ pub struct ProfileUpdateCommand {
     /// will be copied to the currently-updated profile
     #[clap(long, value_hint = clap::ValueHint::DirPath)]
     pub template: Option<PathBuf>,
+
+    /// Set arguments passed to the runtime for web apps in this profile
+    /// {n}Can be specified multiple times, or set to an empty value to clear them
+    #[clap(long, allow_hyphen_values = true)]
+    pub arguments: Option<Vec<String>>,
+
+    /// Set environment variables passed to the runtime for web apps in this profile
+    /// {n}Use the `NAME=VALUE` format, or set to an empty value to clear them
+    #[clap(long)]
+    pub variables: Option<Vec<String>>,
+
+    /// Set Firefox preferences of this profile
+    /// {n}Use the `NAME=VALUE` format, or set to an empty value to clear them
+    #[clap(long)]
+    pub prefs: Option<Vec<String>>,
 }
 
 #[derive(Parser, Debug, Eq, PartialEq, Clone)]
@@ -288,6 +545,154 @@ pub struct RuntimeUninstallCommand {}
 #[derive(Parser, Debug, Eq, PartialEq, Clone)]
 pub struct RuntimePatchCommand {}
 
+#[derive(Parser, Debug, Eq, PartialEq, Clone)]
+pub enum StorageCommand {
+    /// List available storage backups
+    Backups(StorageBackupsCommand),
+
+    /// Restore the storage from a backup
+    Restore(StorageRestoreCommand),
+}
+
+#[derive(Parser, Debug, Eq, PartialEq, Clone)]
+pub struct StorageBackupsCommand {}
+
+#[derive(Parser, Debug, Eq, PartialEq, Clone)]
+pub struct StorageRestoreCommand {
+    /// Backup ID
+    pub backup: String,
+
+    /// Disable any interactive prompts
+    #[clap(short, long)]
+    pub quiet: bool,
+}
+
+#[derive(Parser, Debug, Eq, PartialEq, Clone)]
+pub struct HistoryCommand {
+    /// Only show changes of a web app or profile with this ID
+    pub id: Option<Ulid>,
+
+    /// Only show this number of the most recent changes
+    #[clap(short = 'n', long)]
+    pub limit: Option<usize>,
+}
+
+#[derive(Parser, Debug, Eq, PartialEq, Clone)]
+pub struct DoctorCommand {
+    /// Repair all found problems
+    #[clap(long)]
+    pub fix: bool,
+}
+
+#[derive(Parser, Debug, Eq, PartialEq, Clone)]
+pub struct ApplyCommand {
+    /// Path to the desired-state file
+    /// {n}Supports TOML and JSON files, detected by the file extension
+    #[clap(value_hint = clap::ValueHint::FilePath)]
+    pub file: PathBuf,
+
+    /// Only print the planned changes without applying them
+    #[clap(long)]
+    pub dry_run: bool,
+
+    /// Configuration of the HTTP client
+    #[clap(flatten)]
+    pub client: HTTPClientConfig,
+}
+
+/// A web app specified by its ID, alias, or unambiguous name or domain.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum SiteSelector {
+    /// A web app ID.
+    Id(Ulid),
+
+    /// A web app alias, name or domain.
+    Query(String),
+}
+
+impl FromStr for SiteSelector {
+    type Err = Infallible;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match Ulid::from_string(value) {
+            Ok(id) => Ok(Self::Id(id)),
+            Err(_) => Ok(Self::Query(value.into())),
+        }
+    }
+}
+
+impl From<Ulid> for SiteSelector {
+    fn from(id: Ulid) -> Self {
+        Self::Id(id)
+    }
+}
+
+impl fmt::Display for SiteSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Id(id) => write!(f, "{id}"),
+            Self::Query(query) => write!(f, "{query}"),
+        }
+    }
+}
+
+/// What to do when installing a web app that is already installed.
+#[derive(clap::ValueEnum, Debug, Eq, PartialEq, Clone, Copy, Default)]
+pub enum IfInstalled {
+    /// Fail without installing the web app again.
+    #[default]
+    Refuse,
+
+    /// Update the installed web app with the new settings instead.
+    Update,
+
+    /// Install the web app again as a separate web app.
+    Duplicate,
+}
+
+/// How updates of all web apps are run in the background.
+#[cfg(target_os = "linux")]
+#[derive(clap::ValueEnum, Debug, Eq, PartialEq, Clone, Copy)]
+pub enum UpdateSchedule {
+    /// Run on login and every hour with a systemd user timer.
+    Systemd,
+
+    /// Run on login with an XDG autostart entry.
+    Autostart,
+
+    /// Remove the background updates.
+    None,
+}
+
+/// Parses a duration, such as `30m`, `12h` or `7d`.
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let split = value.find(|char: char| !char.is_ascii_digit()).unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+
+    let number: u64 = number.parse().map_err(|_| format!("invalid duration: {value}"))?;
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(format!("invalid duration unit, expected s, m, h, d or w: {value}")),
+    };
+
+    Ok(Duration::from_secs(number.saturating_mul(seconds)))
+}
+
+/// Parses a protocol handler URL.
+///
+/// Desktop environments can pass local files as paths instead of file URLs,
+/// so absolute paths are converted to file URLs.
+fn parse_protocol_url(value: &str) -> Result<Url, String> {
+    match Path::new(value).is_absolute() {
+        true => Url::from_file_path(value).map_err(|_| format!("invalid file path: {value}")),
+        false => Url::parse(value).map_err(|error| error.to_string()),
+    }
+}
+
 #[derive(Parser, Debug, Eq, PartialEq, Clone)]
 pub struct HTTPClientConfig {
     /// Use a custom user-agent header
@@ -310,3 +715,24 @@ pub struct HTTPClientConfig {
     #[clap(long)]
     pub tls_danger_accept_invalid_hostnames: bool,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("45s"), Ok(Duration::from_secs(45)));
+        assert_eq!(parse_duration("30m"), Ok(Duration::from_secs(30 * 60)));
+        assert_eq!(parse_duration("12h"), Ok(Duration::from_secs(12 * 60 * 60)));
+        assert_eq!(parse_duration("7d"), Ok(Duration::from_secs(7 * 24 * 60 * 60)));
+        assert_eq!(parse_duration("2w"), Ok(Duration::from_secs(2 * 7 * 24 * 60 * 60)));
+    }
+
+    #[test]
+    fn rejects_invalid_durations() {
+        for value in ["", "10", "h", "-5m", "1.5h", "5x", "5 m", "5M", "99999999999999999999d"] {
+            assert!(parse_duration(value).is_err(), "{value:?} was accepted");
+        }
+    }
+}
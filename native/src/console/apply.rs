@@ -0,0 +1,610 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::read_to_string;
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use log::info;
+use serde::Deserialize;
+use serde_json::Value;
+use ulid::Ulid;
+use url::Url;
+
+use crate::components::profile::Profile;
+use crate::components::site::Site;
+use crate::console::app::{
+    ApplyCommand,
+    IfInstalled,
+    ProfileCreateCommand,
+    ProfileRemoveCommand,
+    ProfileUpdateCommand,
+    SiteInstallCommand,
+    SiteUninstallCommand,
+    SiteUpdateCommand,
+};
+use crate::console::{Run, format_assignments};
+use crate::directories::ProjectDirs;
+use crate::storage::Storage;
+
+const STATE_LOAD_ERROR: &str = "Failed to load desired state";
+
+/// Desired profiles and web apps, described in a TOML or JSON file.
+///
+/// Both profiles and web apps are keyed by stable user-chosen keys,
+/// which are mapped to generated IDs when they are first created.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+struct DesiredState {
+    profiles: BTreeMap<String, DesiredProfile>,
+    sites: BTreeMap<String, DesiredSite>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+struct DesiredProfile {
+    name: Option<String>,
+    description: Option<String>,
+    arguments: Vec<String>,
+    variables: BTreeMap<String, String>,
+    prefs: BTreeMap<String, Value>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct DesiredSite {
+    manifest_url: Url,
+    document_url: Option<Url>,
+
+    /// Key of a profile from the same file or ID of an existing profile.
+    ///
+    /// Defaults to the shared profile.
+    profile: Option<String>,
+
+    name: Option<String>,
+    alias: Option<String>,
+    description: Option<String>,
+    start_url: Option<Url>,
+    icon_url: Option<Url>,
+    categories: Option<Vec<String>>,
+    keywords: Option<Vec<String>>,
+
+    #[serde(default)]
+    launch_on_login: bool,
+
+    #[serde(default)]
+    launch_on_browser: bool,
+
+    #[serde(default)]
+    arguments: Vec<String>,
+
+    #[serde(default)]
+    variables: BTreeMap<String, String>,
+}
+
+impl DesiredState {
+    fn load(path: &Path) -> Result<Self> {
+        let data = read_to_string(path).context(STATE_LOAD_ERROR)?;
+
+        if path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("json")) {
+            serde_json::from_str(&data).context(STATE_LOAD_ERROR)
+        } else {
+            toml::from_str(&data).context(STATE_LOAD_ERROR)
+        }
+    }
+
+    /// Resolves the profile of a desired web app.
+    ///
+    /// Returns `None` if the profile does not exist yet,
+    /// but will be created when applying this state.
+    fn resolve_profile(&self, site: &DesiredSite, storage: &Storage) -> Result<Option<Ulid>> {
+        let Some(key) = &site.profile else {
+            return Ok(Some(Ulid::nil()));
+        };
+
+        if self.profiles.contains_key(key) {
+            let id = storage.managed.profiles.get(key);
+            return Ok(id.filter(|id| storage.profiles.contains_key(id)).copied());
+        }
+
+        match Ulid::from_string(key) {
+            Ok(id) if storage.profiles.contains_key(&id) => Ok(Some(id)),
+            _ => bail!("Profile {key} does not exist"),
+        }
+    }
+}
+
+impl DesiredProfile {
+    fn matches(&self, profile: &Profile) -> bool {
+        self.name == profile.name
+            && self.description == profile.description
+            && self.arguments == profile.arguments
+            && self.variables == profile.variables
+            && self.prefs == profile.prefs
+    }
+}
+
+impl DesiredSite {
+    fn document_url(&self) -> Result<Url> {
+        match &self.document_url {
+            Some(url) => Ok(url.clone()),
+            None => Ok(self.manifest_url.join(".")?),
+        }
+    }
+
+    /// Checks whether the web app was installed from the same URLs.
+    ///
+    /// Without the document URL, the manifest URL can also be a page URL,
+    /// in which case it is compared with the page URL of the installed web
+    /// app, as the document URL may differ if the page was redirected.
+    fn installed_from(&self, site: &Site) -> Result<bool> {
+        let config = &site.config;
+        let direct =
+            self.manifest_url == config.manifest_url && self.document_url()? == config.document_url;
+        let discovered = self.document_url.is_none()
+            && (config.page_url.as_ref() == Some(&self.manifest_url)
+                || self.manifest_url == config.document_url);
+        Ok(direct || discovered)
+    }
+
+    fn matches(&self, site: &Site) -> bool {
+        let config = &site.config;
+        self.name == config.name
+            && self.alias == config.alias
+            && self.description == config.description
+            && self.start_url == config.start_url
+            && self.icon_url == config.icon_url
+            && self.categories == config.categories
+            && self.keywords == config.keywords
+            && self.launch_on_login == config.launch_on_login
+            && self.launch_on_browser == config.launch_on_browser
+            && self.arguments == config.arguments
+            && self.variables == config.variables
+    }
+}
+
+/// A single change needed to reach the desired state.
+#[derive(Debug, Eq, PartialEq, Clone)]
+enum Action {
+    CreateProfile { key: String },
+    UpdateProfile { key: String, id: Ulid },
+    RemoveProfile { key: String, id: Ulid },
+    InstallSite { key: String },
+    ReinstallSite { key: String, id: Ulid },
+    UpdateSite { key: String, id: Ulid },
+    UninstallSite { key: String, id: Ulid },
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CreateProfile { key } => write!(f, "Create profile {key}"),
+            Self::UpdateProfile { key, id } => write!(f, "Update profile {key} ({id})"),
+            Self::RemoveProfile { key, id } => write!(f, "Remove profile {key} ({id})"),
+            Self::InstallSite { key } => write!(f, "Install web app {key}"),
+            Self::ReinstallSite { key, id } => write!(f, "Reinstall web app {key} ({id})"),
+            Self::UpdateSite { key, id } => write!(f, "Update web app {key} ({id})"),
+            Self::UninstallSite { key, id } => write!(f, "Uninstall web app {key} ({id})"),
+        }
+    }
+}
+
+/// Compares the storage with the desired state and plans the needed changes.
+///
+/// Only profiles and web apps that were created from a desired-state file
+/// are updated or removed. Other profiles and web apps are left untouched.
+fn plan(state: &DesiredState, storage: &Storage) -> Result<Vec<Action>> {
+    let mut actions = vec![];
+
+    for (key, desired) in &state.profiles {
+        let id = storage.managed.profiles.get(key);
+        match id.and_then(|id| storage.profiles.get(id)) {
+            None => actions.push(Action::CreateProfile { key: key.clone() }),
+            Some(profile) if !desired.matches(profile) => {
+                actions.push(Action::UpdateProfile { key: key.clone(), id: profile.ulid })
+            }
+            _ => {}
+        }
+    }
+
+    for (key, desired) in &state.sites {
+        let profile = state.resolve_profile(desired, storage)?;
+
+        // Changing the manifest, document or profile requires installing the web app again
+        let id = storage.managed.sites.get(key);
+        match id.and_then(|id| storage.sites.get(id)) {
+            None => actions.push(Action::InstallSite { key: key.clone() }),
+            Some(site) if profile != Some(site.profile) || !desired.installed_from(site)? => {
+                actions.push(Action::ReinstallSite { key: key.clone(), id: site.ulid })
+            }
+            Some(site) if !desired.matches(site) => {
+                actions.push(Action::UpdateSite { key: key.clone(), id: site.ulid })
+            }
+            _ => {}
+        }
+    }
+
+    for (key, id) in &storage.managed.sites {
+        if !state.sites.contains_key(key) && storage.sites.contains_key(id) {
+            actions.push(Action::UninstallSite { key: key.clone(), id: *id });
+        }
+    }
+
+    for (key, id) in &storage.managed.profiles {
+        if !state.profiles.contains_key(key) && storage.profiles.contains_key(id) {
+            actions.push(Action::RemoveProfile { key: key.clone(), id: *id });
+        }
+    }
+
+    Ok(actions)
+}
+
+/// Loads the storage, updates its managed keys and writes it back if they changed.
+///
+/// The storage is re-loaded for each change, because commands
+/// that are run when applying also modify the storage.
+fn update_managed(dirs: &ProjectDirs, update: impl FnOnce(&mut Storage)) -> Result<()> {
+    let mut storage = Storage::load(dirs)?;
+    let original = storage.managed.clone();
+
+    update(&mut storage);
+
+    if storage.managed != original {
+        storage.write(dirs)?;
+    }
+
+    Ok(())
+}
+
+impl Run for ApplyCommand {
+    fn run(&self) -> Result<()> {
+        let state = DesiredState::load(&self.file)?;
+        let dirs = ProjectDirs::new()?;
+
+        let _lock = if !self.dry_run { Some(Storage::lock(&dirs)?) } else { None };
+        let storage = Storage::load(&dirs)?;
+        let actions = plan(&state, &storage)?;
+
+        if self.dry_run {
+            if actions.is_empty() {
+                info!("Nothing to apply");
+            }
+            for action in &actions {
+                println!("- {action}");
+            }
+            return Ok(());
+        }
+
+        for action in &actions {
+            info!("{action}");
+            self.execute(action, &state, &dirs)
+                .with_context(|| format!("Failed to apply change: {action}"))?;
+        }
+
+        // Forget keys of profiles and web apps that were removed in the meantime
+        update_managed(&dirs, |storage| {
+            let Storage { managed, profiles, sites, .. } = storage;
+            managed
+                .profiles
+                .retain(|key, id| state.profiles.contains_key(key) && profiles.contains_key(id));
+            managed.sites.retain(|key, id| state.sites.contains_key(key) && sites.contains_key(id));
+        })?;
+
+        info!("Desired state applied!");
+        Ok(())
+    }
+}
+
+impl ApplyCommand {
+    fn execute(&self, action: &Action, state: &DesiredState, dirs: &ProjectDirs) -> Result<()> {
+        match action {
+            Action::CreateProfile { key } => {
+                let desired = &state.profiles[key];
+                let command = ProfileCreateCommand {
+                    name: desired.name.clone(),
+                    description: desired.description.clone(),
+                    template: None,
+                    arguments: Some(desired.arguments.clone()),
+                    variables: Some(format_assignments(&desired.variables)),
+                    prefs: Some(format_assignments(&desired.prefs)),
+                };
+                let id = command._run()?;
+
+                update_managed(dirs, |storage| {
+                    storage.managed.profiles.insert(key.clone(), id);
+                })?;
+            }
+
+            Action::UpdateProfile { key, id } => {
+                let desired = &state.profiles[key];
+                let command = ProfileUpdateCommand {
+                    id: *id,
+                    name: Some(desired.name.clone()),
+                    description: Some(desired.description.clone()),
+                    template: None,
+                    arguments: Some(desired.arguments.clone()),
+                    variables: Some(format_assignments(&desired.variables)),
+                    prefs: Some(format_assignments(&desired.prefs)),
+                };
+                command.run()?;
+            }
+
+            Action::RemoveProfile { key, id } => {
+                let command = ProfileRemoveCommand { id: *id, quiet: true };
+                command.run()?;
+
+                update_managed(dirs, |storage| {
+                    storage.managed.profiles.remove(key);
+                })?;
+            }
+
+            Action::InstallSite { key } => {
+                let id = self.install(&state.sites[key], state, dirs)?;
+
+                update_managed(dirs, |storage| {
+                    storage.managed.sites.insert(key.clone(), id);
+                })?;
+            }
+
+            Action::ReinstallSite { key, id } => {
+                let command = SiteUninstallCommand {
+                    id: (*id).into(),
+                    quiet: true,
+                    system_integration: true,
+                };
+                command.run()?;
+
+                let id = self.install(&state.sites[key], state, dirs)?;
+
+                update_managed(dirs, |storage| {
+                    storage.managed.sites.insert(key.clone(), id);
+                })?;
+            }
+
+            Action::UpdateSite { key, id } => {
+                let desired = &state.sites[key];
+
+                // An array with only an empty string resets the value to the manifest one
+                let reset = || vec!["".into()];
+
+                let command = SiteUpdateCommand {
+                    id: Some((*id).into()),
+                    all: false,
+                    if_older_than: None,
+                    #[cfg(platform_linux)]
+                    schedule: None,
+                    start_url: Some(desired.start_url.clone()),
+                    icon_url: Some(desired.icon_url.clone()),
+                    name: Some(desired.name.clone()),
+                    alias: Some(desired.alias.clone()),
+                    description: Some(desired.description.clone()),
+                    categories: Some(desired.categories.clone().unwrap_or_else(reset)),
+                    keywords: Some(desired.keywords.clone().unwrap_or_else(reset)),
+                    enabled_url_handlers: None,
+                    enabled_protocol_handlers: None,
+                    enabled_file_handlers: None,
+                    client_mode: None,
+                    launch_on_login: Some(desired.launch_on_login),
+                    launch_on_browser: Some(desired.launch_on_browser),
+                    arguments: Some(desired.arguments.clone()),
+                    variables: Some(format_assignments(&desired.variables)),
+                    update_manifest: true,
+                    update_icons: true,
+                    system_integration: true,
+                    accept_manifest_changes: false,
+                    quiet: true,
+                    store_client_config: false,
+                    client: self.client.clone(),
+                };
+                command.run()?;
+            }
+
+            Action::UninstallSite { key, id } => {
+                let command = SiteUninstallCommand {
+                    id: (*id).into(),
+                    quiet: true,
+                    system_integration: true,
+                };
+                command.run()?;
+
+                update_managed(dirs, |storage| {
+                    storage.managed.sites.remove(key);
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn install(
+        &self,
+        desired: &DesiredSite,
+        state: &DesiredState,
+        dirs: &ProjectDirs,
+    ) -> Result<Ulid> {
+        // Profiles are created before web apps, so they must exist at this point
+        let storage = Storage::load(dirs)?;
+        let profile =
+            state.resolve_profile(desired, &storage)?.context("Profile does not exist")?;
+
+        let command = SiteInstallCommand {
+            manifest_url: desired.manifest_url.clone(),
+            document_url: desired.document_url.clone(),
+            profile: Some(profile),
+            start_url: desired.start_url.clone(),
+            icon_url: desired.icon_url.clone(),
+            name: desired.name.clone(),
+            alias: desired.alias.clone(),
+            description: desired.description.clone(),
+            categories: desired.categories.clone(),
+            keywords: desired.keywords.clone(),
+            launch_on_login: Some(desired.launch_on_login),
+            launch_on_browser: Some(desired.launch_on_browser),
+            arguments: Some(desired.arguments.clone()),
+            variables: Some(format_assignments(&desired.variables)),
+            if_installed: IfInstalled::Duplicate,
+            launch_now: false,
+            system_integration: true,
+            store_client_config: false,
+            client: self.client.clone(),
+        };
+        command._run()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::components::site::SiteManifest;
+
+    const MANIFEST_URL: &str = "https://example.com/manifest.json";
+    const DOCUMENT_URL: &str = "https://example.com/";
+
+    fn state(state: Value) -> DesiredState {
+        serde_json::from_value(state).unwrap()
+    }
+
+    fn site(profile: Ulid, config: Value) -> Site {
+        let url = Url::parse(DOCUMENT_URL).unwrap();
+        let mut manifest: SiteManifest = serde_json::from_str(r#"{ "start_url": "/" }"#).unwrap();
+        manifest.process(&url, &url).unwrap();
+
+        Site {
+            ulid: Ulid::new(),
+            profile,
+            config: serde_json::from_value(config).unwrap(),
+            manifest,
+            extras: None,
+            validators: BTreeMap::new(),
+            last_updated: None,
+        }
+    }
+
+    /// Storage with the `work` profile and the `example` web app in it.
+    fn storage(config: Value) -> (Storage, Ulid, Ulid) {
+        let mut storage = Storage::default();
+
+        let profile = Profile::new(Some("Work".into()), None);
+        let site = site(profile.ulid, config);
+        let (profile_id, site_id) = (profile.ulid, site.ulid);
+
+        storage.profiles.insert(profile_id, profile);
+        storage.sites.insert(site_id, site);
+        storage.managed.profiles.insert("work".into(), profile_id);
+        storage.managed.sites.insert("example".into(), site_id);
+
+        (storage, profile_id, site_id)
+    }
+
+    fn desired() -> Value {
+        json!({
+            "profiles": { "work": { "name": "Work" } },
+            "sites": {
+                "example": {
+                    "manifest_url": MANIFEST_URL,
+                    "document_url": DOCUMENT_URL,
+                    "profile": "work",
+                    "name": "Example",
+                },
+            },
+        })
+    }
+
+    fn installed() -> Value {
+        json!({ "manifest_url": MANIFEST_URL, "document_url": DOCUMENT_URL, "name": "Example" })
+    }
+
+    #[test]
+    fn applied_state_has_no_actions() -> Result<()> {
+        let (storage, _, _) = storage(installed());
+        assert!(plan(&state(desired()), &storage)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn missing_state_is_created() -> Result<()> {
+        let actions = plan(&state(desired()), &Storage::default())?;
+
+        assert_eq!(actions, vec![
+            Action::CreateProfile { key: "work".into() },
+            Action::InstallSite { key: "example".into() },
+        ]);
+        Ok(())
+    }
+
+    #[test]
+    fn changed_settings_update_site() -> Result<()> {
+        let (storage, _, id) = storage(installed());
+
+        let mut desired = desired();
+        desired["sites"]["example"]["name"] = json!("Other");
+
+        let actions = plan(&state(desired), &storage)?;
+        assert_eq!(actions, vec![Action::UpdateSite { key: "example".into(), id }]);
+        Ok(())
+    }
+
+    #[test]
+    fn changed_profile_reinstalls_site() -> Result<()> {
+        let (storage, _, id) = storage(installed());
+
+        let mut desired = desired();
+        desired["sites"]["example"]["profile"] = json!(Ulid::nil().to_string());
+
+        let actions = plan(&state(desired), &storage)?;
+        assert_eq!(actions, vec![Action::ReinstallSite { key: "example".into(), id }]);
+        Ok(())
+    }
+
+    #[test]
+    fn changed_manifest_reinstalls_site() -> Result<()> {
+        let (storage, _, id) = storage(installed());
+
+        let mut desired = desired();
+        desired["sites"]["example"]["manifest_url"] = json!("https://example.com/other.json");
+
+        let actions = plan(&state(desired), &storage)?;
+        assert_eq!(actions, vec![Action::ReinstallSite { key: "example".into(), id }]);
+        Ok(())
+    }
+
+    #[test]
+    fn removed_keys_are_uninstalled() -> Result<()> {
+        let (storage, profile, site) = storage(installed());
+
+        let actions = plan(&state(json!({})), &storage)?;
+        assert_eq!(actions, vec![
+            Action::UninstallSite { key: "example".into(), id: site },
+            Action::RemoveProfile { key: "work".into(), id: profile },
+        ]);
+        Ok(())
+    }
+
+    #[test]
+    fn unmanaged_entries_are_untouched() -> Result<()> {
+        let (mut storage, _, _) = storage(installed());
+        storage.managed = Default::default();
+
+        assert!(plan(&state(json!({})), &storage)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn site_installed_from_redirected_page_has_no_actions() -> Result<()> {
+        // The page redirected to another URL, which linked to the manifest
+        let (storage, _, _) = storage(json!({
+            "manifest_url": MANIFEST_URL,
+            "document_url": "https://example.com/home",
+            "page_url": "https://example.com/start",
+            "name": "Example",
+        }));
+
+        let mut desired = desired();
+        desired["sites"]["example"]["manifest_url"] = json!("https://example.com/start");
+        desired["sites"]["example"].as_object_mut().unwrap().remove("document_url");
+
+        assert!(plan(&state(desired), &storage)?.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,306 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs::read_dir;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use log::{info, warn};
+use ulid::Ulid;
+
+use crate::components::profile::Profile;
+use crate::console::Run;
+use crate::console::app::DoctorCommand;
+use crate::directories::ProjectDirs;
+use crate::integrations;
+use crate::storage::Storage;
+
+/// A problem found when checking the storage and system integration.
+#[derive(Debug, Eq, PartialEq, Clone)]
+enum Issue {
+    /// Profile lists a web app that does not exist.
+    MissingSite { profile: Ulid, site: Ulid },
+
+    /// Web app is not listed in its profile.
+    UnlistedSite { profile: Ulid, site: Ulid },
+
+    /// Web app belongs to a profile that does not exist.
+    MissingProfile { profile: Ulid, site: Ulid },
+
+    /// Profile directory exists without a profile.
+    OrphanedProfileDirectory { profile: Ulid },
+
+    /// System integration file exists without a web app.
+    OrphanedIntegration { path: PathBuf },
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingSite { profile, site } => {
+                write!(f, "Profile {profile} contains web app {site} that does not exist")
+            }
+            Self::UnlistedSite { profile, site } => {
+                write!(f, "Web app {site} is not listed in its profile {profile}")
+            }
+            Self::MissingProfile { profile, site } => {
+                write!(f, "Web app {site} belongs to profile {profile} that does not exist")
+            }
+            Self::OrphanedProfileDirectory { profile } => {
+                write!(f, "Profile directory {profile} does not belong to any profile")
+            }
+            Self::OrphanedIntegration { path } => {
+                write!(
+                    f,
+                    "System integration file {} does not belong to any web app",
+                    path.display()
+                )
+            }
+        }
+    }
+}
+
+impl Issue {
+    /// Describes what repairing the issue will do.
+    fn fix_description(&self) -> &'static str {
+        match self {
+            Self::MissingSite { .. } => "Removed the web app from the profile",
+            Self::UnlistedSite { .. } => "Added the web app to its profile",
+            Self::MissingProfile { .. } => "Recreated the profile",
+            Self::OrphanedProfileDirectory { .. } => "Recreated the profile",
+            Self::OrphanedIntegration { .. } => "Removed the file",
+        }
+    }
+}
+
+/// Creates a profile for data that has lost its profile entry.
+///
+/// The profile is only recreated in storage, so its existing data
+/// is kept and can be reviewed or removed as any other profile.
+fn recover_profile(storage: &mut Storage, ulid: Ulid) -> &mut Profile {
+    storage.profiles.entry(ulid).or_insert_with(|| Profile {
+        ulid,
+        name: Some("Recovered profile".into()),
+        description: Some("Profile recreated by the doctor command".into()),
+        ..Profile::default()
+    })
+}
+
+fn check_storage(storage: &Storage) -> Vec<Issue> {
+    let mut issues = vec![];
+
+    for profile in storage.profiles.values() {
+        for site in &profile.sites {
+            if !storage.sites.contains_key(site) {
+                issues.push(Issue::MissingSite { profile: profile.ulid, site: *site });
+            }
+        }
+    }
+
+    for site in storage.sites.values() {
+        match storage.profiles.get(&site.profile) {
+            Some(profile) if !profile.sites.contains(&site.ulid) => {
+                issues.push(Issue::UnlistedSite { profile: profile.ulid, site: site.ulid })
+            }
+            Some(_) => {}
+            None => issues.push(Issue::MissingProfile { profile: site.profile, site: site.ulid }),
+        }
+    }
+
+    issues
+}
+
+fn check_profile_directories(storage: &Storage, dirs: &ProjectDirs) -> Result<Vec<Issue>> {
+    let directory = dirs.userdata.join("profiles");
+    let mut issues = vec![];
+
+    if !directory.exists() {
+        return Ok(issues);
+    }
+
+    for entry in read_dir(directory).context("Failed to read profiles directory")? {
+        let entry = entry.context("Failed to read profiles directory")?;
+
+        // Profile directories are always named by their ID
+        let Some(Ok(profile)) = entry.file_name().to_str().map(Ulid::from_string) else {
+            continue;
+        };
+
+        if entry.path().is_dir() && !storage.profiles.contains_key(&profile) {
+            issues.push(Issue::OrphanedProfileDirectory { profile });
+        }
+    }
+
+    Ok(issues)
+}
+
+fn check_integrations(storage: &Storage) -> Result<Vec<Issue>> {
+    let sites: BTreeSet<Ulid> = storage.sites.keys().copied().collect();
+    let orphans =
+        integrations::find_orphans(&sites).context("Failed to check system integration")?;
+    Ok(orphans.into_iter().map(|path| Issue::OrphanedIntegration { path }).collect())
+}
+
+/// Repairs the issues in the storage and returns the orphaned integration files.
+///
+/// The files are only removed once the repaired storage is written, so
+/// a failed write does not leave the storage unrepaired without them.
+fn fix(storage: &mut Storage, issues: &[Issue]) -> Vec<PathBuf> {
+    let mut orphans = vec![];
+
+    for issue in issues {
+        match issue {
+            Issue::MissingSite { profile, site } => {
+                if let Some(profile) = storage.profiles.get_mut(profile) {
+                    profile.sites.retain(|id| id != site);
+                }
+            }
+            Issue::UnlistedSite { profile, site } => {
+                if let Some(profile) = storage.profiles.get_mut(profile) {
+                    profile.sites.push(*site);
+                }
+            }
+            Issue::MissingProfile { profile, site } => {
+                recover_profile(storage, *profile).sites.push(*site);
+            }
+            Issue::OrphanedProfileDirectory { profile } => {
+                recover_profile(storage, *profile);
+            }
+            Issue::OrphanedIntegration { path } => {
+                orphans.push(path.to_owned());
+            }
+        }
+
+        info!("{issue}: {}", issue.fix_description());
+    }
+
+    orphans
+}
+
+impl Run for DoctorCommand {
+    fn run(&self) -> Result<()> {
+        let dirs = ProjectDirs::new()?;
+        let _lock = if self.fix { Some(Storage::lock(&dirs)?) } else { None };
+        let mut storage = Storage::load(&dirs)?;
+
+        info!("Checking the storage and system integration");
+        let mut issues = check_storage(&storage);
+        issues.extend(check_profile_directories(&storage, &dirs)?);
+        issues.extend(check_integrations(&storage)?);
+
+        if issues.is_empty() {
+            info!("No problems found!");
+            return Ok(());
+        }
+
+        if !self.fix {
+            for issue in &issues {
+                println!("- {issue}");
+            }
+
+            warn!("Found {} problem(s), run with `--fix` to repair them", issues.len());
+            return Ok(());
+        }
+
+        let orphans = fix(&mut storage, &issues);
+        storage.write(&dirs)?;
+        integrations::remove_orphans(&orphans)
+            .context("Failed to remove system integration files")?;
+
+        info!("Repaired {} problem(s)!", issues.len());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde_json::json;
+    use url::Url;
+
+    use super::*;
+    use crate::components::site::{Site, SiteManifest};
+
+    fn site(profile: Ulid) -> Site {
+        let url = Url::parse("https://example.com/").unwrap();
+        let mut manifest: SiteManifest = serde_json::from_str(r#"{ "start_url": "/" }"#).unwrap();
+        manifest.process(&url, &url).unwrap();
+
+        let config = json!({ "document_url": url, "manifest_url": url });
+        Site {
+            ulid: Ulid::new(),
+            profile,
+            config: serde_json::from_value(config).unwrap(),
+            manifest,
+            extras: None,
+            validators: BTreeMap::new(),
+            last_updated: None,
+        }
+    }
+
+    /// Storage with a web app in the default profile.
+    fn storage() -> (Storage, Ulid) {
+        let mut storage = Storage::default();
+        let site = site(Ulid::nil());
+        let id = site.ulid;
+
+        storage.profiles.get_mut(&Ulid::nil()).unwrap().sites.push(id);
+        storage.sites.insert(id, site);
+        (storage, id)
+    }
+
+    #[test]
+    fn consistent_storage_has_no_issues() {
+        let (storage, _) = storage();
+        assert!(check_storage(&storage).is_empty());
+    }
+
+    #[test]
+    fn finds_missing_site() {
+        let (mut storage, site) = storage();
+        storage.sites.remove(&site);
+
+        let issues = check_storage(&storage);
+        assert_eq!(issues, [Issue::MissingSite { profile: Ulid::nil(), site }]);
+
+        fix(&mut storage, &issues);
+        assert!(check_storage(&storage).is_empty());
+        assert!(storage.profiles[&Ulid::nil()].sites.is_empty());
+    }
+
+    #[test]
+    fn finds_unlisted_site() {
+        let (mut storage, site) = storage();
+        storage.profiles.get_mut(&Ulid::nil()).unwrap().sites.clear();
+
+        let issues = check_storage(&storage);
+        assert_eq!(issues, [Issue::UnlistedSite { profile: Ulid::nil(), site }]);
+
+        fix(&mut storage, &issues);
+        assert!(check_storage(&storage).is_empty());
+    }
+
+    #[test]
+    fn finds_missing_profile() {
+        let (mut storage, site) = storage();
+        let profile = Ulid::new();
+        storage.profiles.get_mut(&Ulid::nil()).unwrap().sites.clear();
+        storage.sites.get_mut(&site).unwrap().profile = profile;
+
+        let issues = check_storage(&storage);
+        assert_eq!(issues, [Issue::MissingProfile { profile, site }]);
+
+        fix(&mut storage, &issues);
+        assert!(check_storage(&storage).is_empty());
+        assert_eq!(storage.profiles[&profile].sites, [site]);
+    }
+
+    #[test]
+    fn fix_returns_orphaned_integration_files() {
+        let (mut storage, _) = storage();
+        let path = PathBuf::from("/tmp/orphan.desktop");
+
+        let orphans = fix(&mut storage, &[Issue::OrphanedIntegration { path: path.clone() }]);
+        assert_eq!(orphans, [path]);
+    }
+}
@@ -1,12 +1,33 @@
-use anyhow::Result;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::io;
+use std::path::{PathBuf, absolute};
 
+use anyhow::{Context, Result, bail};
+use clap::CommandFactory;
+use clap_complete::engine::{ArgValueCandidates, CompletionCandidate};
+use reqwest::blocking::Client;
+use serde_json::Value;
+
+use crate::components::site::SiteClientConfig;
 pub use crate::console::app::App;
-use crate::console::app::{ProfileCommand, RuntimeCommand, SiteCommand};
+use crate::console::app::{
+    HTTPClientConfig,
+    ProfileCommand,
+    RuntimeCommand,
+    SiteCommand,
+    StorageCommand,
+};
+use crate::directories::ProjectDirs;
+use crate::storage::Storage;
 
 pub mod app;
+pub mod apply;
+pub mod doctor;
 pub mod profile;
 pub mod runtime;
 pub mod site;
+pub mod storage;
 
 /// Parses and stores `Option<Option<X>>` parameters.
 ///
@@ -50,6 +71,187 @@ macro_rules! store_value_vec {
 pub(in crate::console) use store_value;
 pub(in crate::console) use store_value_vec;
 
+/// Parses environment variables in the `NAME=VALUE` format.
+///
+/// Empty strings are skipped, so a list with only an empty
+/// string can be used to clear all variables.
+pub(in crate::console) fn parse_variables(
+    variables: &[String],
+) -> Result<BTreeMap<String, String>> {
+    variables
+        .iter()
+        .filter(|variable| !variable.is_empty())
+        .map(|variable| {
+            let (name, value) = variable
+                .split_once('=')
+                .with_context(|| format!("Invalid environment variable: {variable}"))?;
+            Ok((name.into(), value.into()))
+        })
+        .collect()
+}
+
+/// Formats a map into a list of `NAME=VALUE` strings.
+///
+/// Used to pass maps to console commands, which accept
+/// them in the same format as the CLI.
+pub(crate) fn format_assignments<T: Display>(map: &BTreeMap<String, T>) -> Vec<String> {
+    map.iter().map(|(name, value)| format!("{name}={value}")).collect()
+}
+
+impl HTTPClientConfig {
+    /// Whether any HTTP client option was set.
+    pub fn is_set(&self) -> bool {
+        self.user_agent.is_some()
+            || self.tls_root_certificates_der.is_some()
+            || self.tls_root_certificates_pem.is_some()
+            || self.tls_danger_accept_invalid_certs
+            || self.tls_danger_accept_invalid_hostnames
+    }
+
+    /// Converts the options into a configuration that can be stored for a web app.
+    ///
+    /// Certificate paths are made absolute, so the configuration also works for updates
+    /// that run from a different working directory. Returns nothing when no option was
+    /// set, so the stored configuration is removed.
+    pub fn to_stored(&self) -> Result<Option<SiteClientConfig>> {
+        if !self.is_set() {
+            return Ok(None);
+        }
+
+        let resolve = |paths: &Option<Vec<PathBuf>>| -> Result<Option<Vec<PathBuf>>> {
+            let Some(paths) = paths else {
+                return Ok(None);
+            };
+
+            let paths = paths.iter().map(absolute).collect::<io::Result<_>>();
+            Ok(Some(paths.context("Failed to resolve certificate path")?))
+        };
+
+        Ok(Some(SiteClientConfig {
+            user_agent: self.user_agent.clone(),
+            tls_root_certificates_der: resolve(&self.tls_root_certificates_der)?,
+            tls_root_certificates_pem: resolve(&self.tls_root_certificates_pem)?,
+            tls_danger_accept_invalid_certs: self.tls_danger_accept_invalid_certs,
+            tls_danger_accept_invalid_hostnames: self.tls_danger_accept_invalid_hostnames,
+        }))
+    }
+
+    /// Converts the options back into command-line arguments.
+    ///
+    /// Used to run the same command later, so certificate paths are made absolute.
+    #[cfg(platform_linux)]
+    pub fn to_arguments(&self) -> Result<Vec<String>> {
+        let Some(stored) = self.to_stored()? else {
+            return Ok(vec![]);
+        };
+
+        let mut arguments = vec![];
+
+        if let Some(user_agent) = stored.user_agent {
+            arguments.extend(["--user-agent".into(), user_agent]);
+        }
+
+        let certificates = [
+            ("--tls-root-certificates-der", stored.tls_root_certificates_der),
+            ("--tls-root-certificates-pem", stored.tls_root_certificates_pem),
+        ];
+        for (flag, paths) in certificates {
+            for path in paths.into_iter().flatten() {
+                arguments.extend([flag.into(), path.display().to_string()]);
+            }
+        }
+
+        let flags = [
+            (stored.tls_danger_accept_invalid_certs, "--tls-danger-accept-invalid-certs"),
+            (stored.tls_danger_accept_invalid_hostnames, "--tls-danger-accept-invalid-hostnames"),
+        ];
+        arguments.extend(flags.into_iter().filter(|(set, _)| *set).map(|(_, flag)| flag.into()));
+
+        Ok(arguments)
+    }
+
+    /// Constructs the HTTP client for a web app.
+    ///
+    /// Options set for the command take precedence over the configuration
+    /// stored for the web app, which is only used when no option was set.
+    pub fn construct(&self, stored: Option<&SiteClientConfig>) -> Result<Client> {
+        match stored {
+            Some(stored) if !self.is_set() => stored.construct(),
+            _ => self.to_stored()?.unwrap_or_default().construct(),
+        }
+    }
+}
+
+/// Parses Firefox preferences in the `NAME=VALUE` format.
+///
+/// Values are parsed as JSON booleans, integers or strings. Other
+/// values that are not valid JSON are treated as plain strings. Empty
+/// strings are skipped, so a list with only an empty string can be
+/// used to clear all preferences.
+pub(in crate::console) fn parse_prefs(prefs: &[String]) -> Result<BTreeMap<String, Value>> {
+    prefs
+        .iter()
+        .filter(|pref| !pref.is_empty())
+        .map(|pref| {
+            let (name, value) =
+                pref.split_once('=').with_context(|| format!("Invalid preference: {pref}"))?;
+
+            let value = match serde_json::from_str(value) {
+                Ok(value @ (Value::Bool(_) | Value::String(_))) => value,
+                Ok(value @ Value::Number(_)) if value.is_i64() => value,
+                Ok(_) => bail!("Invalid preference value: {pref}"),
+                Err(_) => Value::String(value.into()),
+            };
+
+            Ok((name.into(), value))
+        })
+        .collect()
+}
+
+/// Builds the CLI command with dynamic completions.
+///
+/// Web app arguments are completed with IDs and aliases
+/// of installed web apps, which are loaded from the storage.
+pub fn command() -> clap::Command {
+    // Arguments are changed in place, because `mut_arg` would move the ID after other positionals
+    let site_id = |cmd: clap::Command| {
+        cmd.mut_args(|arg| match arg.get_id() == "id" {
+            true => arg.add(ArgValueCandidates::new(site_candidates)),
+            false => arg,
+        })
+    };
+
+    App::command().mut_subcommand("site", |cmd| {
+        cmd.mut_subcommand("launch", site_id)
+            .mut_subcommand("uninstall", site_id)
+            .mut_subcommand("update", site_id)
+            .mut_subcommand("move", site_id)
+            .mut_subcommand("copy", site_id)
+            .mut_subcommand("share", site_id)
+            .mut_subcommand("kill", site_id)
+    })
+}
+
+/// Lists IDs and aliases of installed web apps for completions.
+fn site_candidates() -> Vec<CompletionCandidate> {
+    let Ok(storage) = ProjectDirs::new().and_then(|dirs| Storage::load_sites(&dirs)) else {
+        return vec![];
+    };
+
+    storage
+        .sites
+        .values()
+        .flat_map(|site| {
+            let name = site.name();
+            let alias = site.config.alias.clone();
+            let id =
+                CompletionCandidate::new(site.ulid.to_string()).help(Some(name.clone().into()));
+            let alias = alias.map(|alias| CompletionCandidate::new(alias).help(Some(name.into())));
+            alias.into_iter().chain([id])
+        })
+        .collect()
+}
+
 pub trait Run {
     fn run(&self) -> Result<()>;
 }
@@ -61,6 +263,10 @@ impl Run for App {
             App::Site(cmd) => cmd.run(),
             App::Profile(cmd) => cmd.run(),
             App::Runtime(cmd) => cmd.run(),
+            App::Storage(cmd) => cmd.run(),
+            App::Doctor(cmd) => cmd.run(),
+            App::Apply(cmd) => cmd.run(),
+            App::History(cmd) => cmd.run(),
         }
     }
 }
@@ -73,6 +279,12 @@ impl Run for SiteCommand {
             SiteCommand::Install(cmd) => cmd.run(),
             SiteCommand::Uninstall(cmd) => cmd.run(),
             SiteCommand::Update(cmd) => cmd.run(),
+            SiteCommand::Move(cmd) => cmd.run(),
+            SiteCommand::Copy(cmd) => cmd.run(),
+            SiteCommand::Share(cmd) => cmd.run(),
+            SiteCommand::Ps(cmd) => cmd.run(),
+            SiteCommand::Kill(cmd) => cmd.run(),
+            SiteCommand::Lint(cmd) => cmd.run(),
         }
     }
 }
@@ -99,3 +311,13 @@ impl Run for RuntimeCommand {
         }
     }
 }
+
+impl Run for StorageCommand {
+    #[inline]
+    fn run(&self) -> Result<()> {
+        match self {
+            StorageCommand::Backups(cmd) => cmd.run(),
+            StorageCommand::Restore(cmd) => cmd.run(),
+        }
+    }
+}
@@ -15,7 +15,7 @@ use crate::console::app::{
     ProfileRemoveCommand,
     ProfileUpdateCommand,
 };
-use crate::console::{Run, store_value};
+use crate::console::{Run, parse_prefs, parse_variables, store_value};
 use crate::directories::ProjectDirs;
 use crate::integrations;
 use crate::integrations::IntegrationUninstallArgs;
@@ -41,6 +41,26 @@ fn apply_profile_template(
     Ok(())
 }
 
+fn store_profile_runtime(
+    profile: &mut Profile,
+    arguments: &Option<Vec<String>>,
+    variables: &Option<Vec<String>>,
+    prefs: &Option<Vec<String>>,
+) -> Result<()> {
+    if let Some(arguments) = arguments {
+        profile.arguments =
+            arguments.iter().filter(|argument| !argument.is_empty()).cloned().collect();
+    }
+    if let Some(variables) = variables {
+        profile.variables = parse_variables(variables)?;
+    }
+    if let Some(prefs) = prefs {
+        profile.prefs = parse_prefs(prefs)?;
+    }
+
+    Ok(())
+}
+
 impl Run for ProfileListCommand {
     fn run(&self) -> Result<()> {
         let dirs = ProjectDirs::new()?;
@@ -62,7 +82,11 @@ impl Run for ProfileListCommand {
             }
 
             for site in profile.sites {
-                let site = storage.sites.get(&site).context("Profile with invalid web app")?;
+                let Some(site) = storage.sites.get(&site) else {
+                    warn!("Profile contains web app {site} that does not exist");
+                    warn!("Run `firefoxpwa doctor` to check and repair the storage");
+                    continue;
+                };
 
                 let url = if site.config.manifest_url.scheme() != "data" {
                     &site.config.manifest_url
@@ -90,17 +114,20 @@ impl Run for ProfileCreateCommand {
 impl ProfileCreateCommand {
     pub fn _run(&self) -> Result<Ulid> {
         let dirs = ProjectDirs::new()?;
+        let _lock = Storage::lock(&dirs)?;
         let mut storage = Storage::load(&dirs)?;
 
         info!("Creating the profile");
 
-        let profile = Profile::new(self.name.clone(), self.description.clone());
+        let mut profile = Profile::new(self.name.clone(), self.description.clone());
         let ulid = profile.ulid;
+        store_profile_runtime(&mut profile, &self.arguments, &self.variables, &self.prefs)?;
 
-        storage.profiles.insert(ulid, profile);
+        storage.profiles.insert(ulid, profile.clone());
         storage.write(&dirs)?;
 
         apply_profile_template(&self.template, &ulid, &dirs)?;
+        profile.write_prefs(&dirs)?;
 
         info!("Profile created: {ulid}");
         Ok(ulid)
@@ -110,6 +137,7 @@ impl ProfileCreateCommand {
 impl Run for ProfileRemoveCommand {
     fn run(&self) -> Result<()> {
         let dirs = ProjectDirs::new()?;
+        let _lock = Storage::lock(&dirs)?;
         let mut storage = Storage::load(&dirs)?;
 
         let profile = storage.profiles.get_mut(&self.id).context("Profile does not exist")?;
@@ -166,6 +194,7 @@ impl Run for ProfileRemoveCommand {
 impl Run for ProfileUpdateCommand {
     fn run(&self) -> Result<()> {
         let dirs = ProjectDirs::new()?;
+        let _lock = Storage::lock(&dirs)?;
         let mut storage = Storage::load(&dirs)?;
 
         let profile = storage.profiles.get_mut(&self.id).context("Profile does not exist")?;
@@ -173,9 +202,12 @@ impl Run for ProfileUpdateCommand {
         info!("Updating the profile");
         store_value!(profile.name, self.name);
         store_value!(profile.description, self.description);
+        store_profile_runtime(profile, &self.arguments, &self.variables, &self.prefs)?;
+        let profile = profile.clone();
         storage.write(&dirs)?;
 
         apply_profile_template(&self.template, &self.id, &dirs)?;
+        profile.write_prefs(&dirs)?;
 
         info!("Profile updated!");
         Ok(())
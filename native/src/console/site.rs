@@ -1,40 +1,147 @@
-use std::fs::metadata;
+use std::collections::BTreeMap;
+use std::fs::{metadata, remove_file};
 use std::io;
 use std::io::Write;
+use std::path::{PathBuf, absolute};
+use std::time::{Duration, UNIX_EPOCH};
 
 use anyhow::{Context, Result, bail};
-use cfg_if::cfg_if;
-use log::{info, warn};
+use log::{error, info, warn};
 use ulid::Ulid;
 use url::Url;
 
+use crate::components::discovery::resolve_urls;
+use crate::components::lint::{LintReport, lint};
+use crate::components::manifest::{FileLaunchType, ManifestDiff};
 use crate::components::runtime::Runtime;
-use crate::components::site::{Site, SiteConfig};
+use crate::components::share::{ShareData, ShareRequest};
+use crate::components::site::{LaunchOptions, Site, SiteConfig};
+#[cfg(platform_linux)]
+use crate::console::app::UpdateSchedule;
 use crate::console::app::{
+    IfInstalled,
+    SiteCopyCommand,
     SiteInstallCommand,
+    SiteKillCommand,
     SiteLaunchCommand,
+    SiteLintCommand,
+    SiteMoveCommand,
+    SitePsCommand,
+    SiteSelector,
+    SiteShareCommand,
     SiteUninstallCommand,
     SiteUpdateCommand,
 };
-use crate::console::{Run, store_value, store_value_vec};
+use crate::console::{Run, parse_variables, store_value, store_value_vec};
 use crate::directories::ProjectDirs;
 use crate::integrations;
 use crate::integrations::{IntegrationInstallArgs, IntegrationUninstallArgs};
-use crate::storage::Storage;
-use crate::utils::construct_certificates_and_client;
+use crate::storage::{Instance, InstanceRegistry, Storage};
+use crate::utils::format_time;
+
+impl SiteSelector {
+    /// Resolves the selector into a web app ID.
+    ///
+    /// IDs are returned directly, and queries are resolved from web apps
+    /// loaded without their profiles. See [`resolve_in`](Self::resolve_in).
+    pub fn resolve(&self, dirs: &ProjectDirs) -> Result<Ulid> {
+        match self {
+            Self::Id(id) => Ok(*id),
+            Self::Query(_) => self.resolve_in(&Storage::load_sites(dirs)?),
+        }
+    }
+
+    /// Resolves the selector into a web app ID from the already loaded storage.
+    ///
+    /// Queries first match web app aliases, then names and then domains,
+    /// all compared case-insensitively. Names and domains must match
+    /// a single web app, so the result is never ambiguous.
+    pub fn resolve_in(&self, storage: &Storage) -> Result<Ulid> {
+        let query = match self {
+            Self::Id(id) => return Ok(*id),
+            Self::Query(query) => query.to_lowercase(),
+        };
+
+        let sites: Vec<&Site> = storage.sites.values().collect();
+
+        if let Some(site) = sites.iter().find(|site| {
+            site.config.alias.as_ref().is_some_and(|alias| alias.to_lowercase() == query)
+        }) {
+            return Ok(site.ulid);
+        }
+
+        let matchers: [fn(&Site) -> String; 2] = [Site::name, Site::domain];
+        for matcher in matchers {
+            let matches: Vec<Ulid> = sites
+                .iter()
+                .filter(|site| matcher(site).to_lowercase() == query)
+                .map(|site| site.ulid)
+                .collect();
+
+            match matches.as_slice() {
+                [] => continue,
+                [id] => return Ok(*id),
+                ids => {
+                    let ids: Vec<String> = ids.iter().map(Ulid::to_string).collect();
+                    bail!("Multiple web apps match {self}: {}", ids.join(", "));
+                }
+            }
+        }
+
+        bail!("No web app matches {self}")
+    }
+}
+
+/// Checks that the alias is valid and not used by any other web app.
+fn check_alias(storage: &Storage, id: Ulid, alias: &str) -> Result<()> {
+    if alias.chars().any(char::is_whitespace) {
+        bail!("Web app alias cannot contain whitespace");
+    }
+
+    if Ulid::from_string(alias).is_ok() {
+        bail!("Web app alias cannot be a valid web app ID");
+    }
+
+    let alias = alias.to_lowercase();
+    for site in storage.sites.values() {
+        if site.ulid != id
+            && site.config.alias.as_ref().map(|alias| alias.to_lowercase()) == Some(alias.clone())
+        {
+            bail!("Web app alias is already used by web app {}", site.ulid);
+        }
+    }
+
+    Ok(())
+}
+
+/// Finds the same web app that is already installed in the profile.
+///
+/// Web apps are the same when their manifests have the same identity.
+fn find_installed(storage: &Storage, site: &Site, profile: Ulid) -> Option<Ulid> {
+    let identity = site.identity();
+    let mut sites = storage.sites.values();
+    let existing =
+        sites.find(|existing| existing.profile == profile && existing.identity() == identity);
+    existing.map(|existing| existing.ulid)
+}
 
 impl Run for SiteLaunchCommand {
     fn run(&self) -> Result<()> {
         let dirs = ProjectDirs::new()?;
-        let storage = Storage::load(&dirs)?;
+        let id = self.id.resolve(&dirs)?;
+        let storage = Storage::load_site(&dirs, id)?;
+
+        let site = storage.sites.get(&id).context("Web app does not exist")?;
+        if !self.arguments.is_empty() {
+            storage.policies.ensure_unlocked("arguments")?;
+        }
 
-        let site = storage.sites.get(&self.id).context("Web app does not exist")?;
         let args = if !&self.arguments.is_empty() { &self.arguments } else { &storage.arguments };
 
         #[cfg(platform_macos)]
         {
             if !self.direct_launch {
-                integrations::launch(site, &self.url, args)?;
+                integrations::launch(site, &self.url, &self.file, self.share.as_deref(), args)?;
                 return Ok(());
             }
         }
@@ -102,9 +209,20 @@ impl Run for SiteLaunchCommand {
             profile.patch(&dirs)?;
         }
 
+        // Desktop environments open files through the protocol handler argument
+        let mut files = self.file.to_owned();
+        if let Some(Some(protocol)) = &self.protocol
+            && protocol.scheme() == "file"
+        {
+            let path = protocol.to_file_path().ok().context("Invalid file URL")?;
+            files.push(path);
+        }
+
         // Handle protocol handler URLs
         // See: https://html.spec.whatwg.org/multipage/system-state.html#protocol-handler-invocation
-        let handler = if let Some(Some(protocol)) = &self.protocol {
+        let handler = if let Some(Some(protocol)) = &self.protocol
+            && protocol.scheme() != "file"
+        {
             let scheme = protocol.scheme().to_string();
             let input = urlencoding::encode(protocol.as_str());
 
@@ -135,17 +253,60 @@ impl Run for SiteLaunchCommand {
             None
         };
 
-        let url = match handler {
-            Some(url) => vec![url],
-            None => self.url.to_owned(),
+        let (url, files) = match handler {
+            Some(url) => (vec![url], vec![]),
+            None if !files.is_empty() => file_launches(site, &files)?.into_iter().unzip(),
+            None => (self.url.to_owned(), vec![]),
         };
 
-        info!("Launching the web app");
-        cfg_if! {
-            if #[cfg(platform_macos)] {
-                site.launch(&dirs, &runtime, &storage.config, &url, args, storage.variables)?.wait()?;
-            } else {
-                site.launch(&dirs, &runtime, &storage.config, &url, args, storage.variables)?;
+        // Hand the launch to the running instance instead of starting another runtime
+        let instances = InstanceRegistry::new(&dirs);
+        let instance = instances.find(site.ulid, profile.ulid);
+
+        match &instance {
+            Some(instance) => {
+                info!("Opening the web app in the running instance ({})", instance.pid)
+            }
+            None => info!("Launching the web app"),
+        }
+
+        let options = LaunchOptions {
+            urls: &url,
+            files: &files,
+            share: self.share.as_deref(),
+            arguments: args,
+            variables: &storage.variables,
+            policies: &storage.policies,
+            instance: instance.as_ref(),
+        };
+        let child = site.launch(&dirs, &runtime, &storage.config, profile, &options)?;
+
+        // The running instance could have exited before it received the launch,
+        // in which case the launched process became the runtime under its remoting name
+        let handed_off = instance.as_ref().is_some_and(Instance::is_running);
+        let registered = match &instance {
+            None => instances.register(site.ulid, profile.ulid, child.id()).map(drop),
+            Some(instance) if !handed_off => {
+                let instance = Instance { pid: child.id(), ..instance.clone() };
+                instances.attach(site.ulid, &instance).map(drop)
+            }
+            Some(instance) if instance.site != site.ulid => {
+                instances.attach(site.ulid, instance).map(drop)
+            }
+            Some(_) => Ok(()),
+        };
+
+        if let Err(error) = registered {
+            warn!("{error:?}");
+        }
+
+        // The macOS launcher waits for the runtime, so it can unregister it when it exits
+        #[cfg(platform_macos)]
+        {
+            let mut child = child;
+            child.wait()?;
+            if !handed_off {
+                instances.remove(site.ulid);
             }
         }
 
@@ -153,6 +314,82 @@ impl Run for SiteLaunchCommand {
     }
 }
 
+/// Groups the files by the file handler actions they are opened with.
+///
+/// Handlers with the `single-client` launch type receive all their files
+/// in one launch, and other handlers receive each file in a separate one.
+/// Files are matched by their extensions against enabled file handlers.
+/// See: https://wicg.github.io/manifest-incubations/#launching-a-file-handler
+fn file_launches(site: &Site, files: &[PathBuf]) -> Result<Vec<(Url, Vec<PathBuf>)>> {
+    let mut launches: Vec<(Url, Vec<PathBuf>)> = vec![];
+
+    for file in files {
+        let path = absolute(file).context("Failed to resolve file path")?;
+        if !path.is_file() {
+            bail!("File {} does not exist", path.display());
+        }
+
+        let filename = path.file_name().unwrap_or_default().to_string_lossy();
+        let enabled = &site.config.enabled_file_handlers;
+        let handler = site
+            .file_handlers()
+            .iter()
+            .find(|handler| handler.accepts(&filename, enabled))
+            .with_context(|| format!("No enabled file handler for {}", path.display()))?;
+
+        let existing = launches.iter_mut().find(|(action, _)| *action == handler.action);
+        match (handler.launch_type, existing) {
+            (FileLaunchType::SingleClient, Some((_, paths))) => paths.push(path),
+            _ => launches.push((handler.action.clone(), vec![path])),
+        }
+    }
+
+    Ok(launches)
+}
+
+impl Run for SiteShareCommand {
+    fn run(&self) -> Result<()> {
+        let dirs = ProjectDirs::new()?;
+        let id = self.id.resolve(&dirs)?;
+        let storage = Storage::load_site(&dirs, id)?;
+
+        let site = storage.sites.get(&id).context("Web app does not exist")?;
+        let target = site.share_target().context("Web app does not support sharing")?;
+
+        let data = ShareData {
+            title: self.title.clone(),
+            text: self.text.clone(),
+            url: self.url.clone().flatten(),
+            files: self.file.clone(),
+        };
+
+        let request = ShareRequest::new(site, target, &data)?;
+        let share = request.persist(&dirs)?;
+
+        info!("Sharing with the web app");
+        let command = SiteLaunchCommand {
+            id: id.into(),
+            url: vec![request.url().clone()],
+            protocol: None,
+            file: vec![],
+            share: share.clone(),
+            arguments: vec![],
+            #[cfg(platform_macos)]
+            direct_launch: false,
+        };
+
+        // The runtime removes the request once it is loaded, so it only needs to be removed on failure
+        let result = command.run();
+        if result.is_err()
+            && let Some(share) = share
+        {
+            let _ = remove_file(share);
+        }
+
+        result
+    }
+}
+
 impl Run for SiteInstallCommand {
     fn run(&self) -> Result<()> {
         self._run()?;
@@ -166,46 +403,78 @@ impl SiteInstallCommand {
             bail!("The document URL is required when the manifest URL is a data URL");
         }
 
+        let client = self.client.construct(None)?;
+
+        // Without the document URL, the URL can also be a page that links to the manifest
+        let (manifest_url, document_url) =
+            resolve_urls(&self.manifest_url, self.document_url.as_ref(), &client)?;
+
+        // Manifests and icons are downloaded without holding the lock, so other commands
+        // are not blocked by them, and the storage is loaded again before it is changed
         let dirs = ProjectDirs::new()?;
-        let mut storage = Storage::load(&dirs)?;
+        let storage = Storage::load(&dirs)?;
 
         let profile = storage
             .profiles
-            .get_mut(&self.profile.unwrap_or_else(Ulid::nil))
-            .context("Profile does not exist")?;
+            .get(&self.profile.unwrap_or_else(Ulid::nil))
+            .context("Profile does not exist")?
+            .ulid;
 
         info!("Installing the web app");
 
+        let alias = self.alias.clone().filter(|alias| !alias.is_empty());
+
         let config = SiteConfig {
             name: self.name.clone(),
+            alias,
             description: self.description.clone(),
             categories: self.categories.clone(),
             keywords: self.keywords.clone(),
-            document_url: match &self.document_url {
-                Some(url) => url.clone(),
-                None => self.manifest_url.join(".")?,
-            },
-            manifest_url: self.manifest_url.clone(),
+            page_url: (self.document_url.is_none() && manifest_url != self.manifest_url)
+                .then(|| self.manifest_url.clone()),
+            document_url,
+            manifest_url,
             start_url: self.start_url.clone(),
             icon_url: self.icon_url.clone(),
             enabled_url_handlers: vec![],
             enabled_protocol_handlers: vec![],
+            enabled_file_handlers: vec![],
+            client_mode: None,
             custom_protocol_handlers: vec![],
             launch_on_login: self.launch_on_login.unwrap_or(false),
             launch_on_browser: self.launch_on_browser.unwrap_or(false),
+            arguments: match &self.arguments {
+                Some(arguments) => {
+                    arguments.iter().filter(|argument| !argument.is_empty()).cloned().collect()
+                }
+                None => vec![],
+            },
+            variables: match &self.variables {
+                Some(variables) => parse_variables(variables)?,
+                None => BTreeMap::new(),
+            },
+            client: if self.store_client_config { self.client.to_stored()? } else { None },
         };
 
-        let client = construct_certificates_and_client(
-            self.client.user_agent.as_deref(),
-            &self.client.tls_root_certificates_der,
-            &self.client.tls_root_certificates_pem,
-            self.client.tls_danger_accept_invalid_certs,
-            self.client.tls_danger_accept_invalid_hostnames,
-        )?;
-
-        let site = Site::new(profile.ulid, config, &client)?;
+        let site = Site::new(profile, config, &client)?;
         let ulid = site.ulid;
 
+        if let Some(existing) = find_installed(&storage, &site, profile) {
+            match self.if_installed {
+                IfInstalled::Refuse => {
+                    bail!("Web app is already installed in this profile: {existing}")
+                }
+                IfInstalled::Update => return self.update_existing(existing),
+                IfInstalled::Duplicate => {
+                    warn!("Web app is already installed in this profile: {existing}");
+                }
+            }
+        }
+
+        if let Some(alias) = &site.config.alias {
+            check_alias(&storage, Ulid::nil(), alias)?;
+        }
+
         if self.system_integration {
             info!("Installing system integration");
             integrations::install(&IntegrationInstallArgs {
@@ -219,34 +488,106 @@ impl SiteInstallCommand {
             .context("Failed to install system integration")?;
         }
 
-        profile.sites.push(ulid);
-        storage.sites.insert(ulid, site);
-        storage.write(&dirs)?;
+        if let Err(error) = Self::store(&dirs, &site) {
+            if self.system_integration {
+                let args = IntegrationUninstallArgs { site: &site, dirs: &dirs };
+                let _ = integrations::uninstall(&args);
+            }
+            return Err(error);
+        }
 
         info!("Web app installed: {ulid}");
 
         if self.launch_now {
-            let command = SiteLaunchCommand {
-                id: ulid,
-                url: vec![],
-                protocol: None,
-                arguments: vec![],
-                #[cfg(platform_macos)]
-                direct_launch: false,
-            };
-            command.run()?;
+            self.launch(ulid)?;
         }
 
         Ok(ulid)
     }
+
+    /// Stores the installed web app, checking it again against the current storage.
+    fn store(dirs: &ProjectDirs, site: &Site) -> Result<()> {
+        let _lock = Storage::lock(dirs)?;
+        let mut storage = Storage::load(dirs)?;
+
+        if let Some(alias) = &site.config.alias {
+            check_alias(&storage, Ulid::nil(), alias)?;
+        }
+
+        let profile = storage.profiles.get_mut(&site.profile).context("Profile does not exist")?;
+        profile.sites.push(site.ulid);
+        storage.sites.insert(site.ulid, site.clone());
+        storage.write(dirs)
+    }
+
+    /// Updates the already installed web app with the specified settings.
+    ///
+    /// Only settings that were specified are changed. Sensitive manifest
+    /// changes are not accepted, and need to be approved with the update command.
+    fn update_existing(&self, id: Ulid) -> Result<Ulid> {
+        info!("Updating the installed web app instead: {id}");
+
+        let command = SiteUpdateCommand {
+            id: Some(id.into()),
+            all: false,
+            if_older_than: None,
+            #[cfg(platform_linux)]
+            schedule: None,
+            start_url: self.start_url.clone().map(Some),
+            icon_url: self.icon_url.clone().map(Some),
+            name: self.name.clone().map(Some),
+            alias: self.alias.clone().filter(|alias| !alias.is_empty()).map(Some),
+            description: self.description.clone().map(Some),
+            categories: self.categories.clone(),
+            keywords: self.keywords.clone(),
+            enabled_url_handlers: None,
+            enabled_protocol_handlers: None,
+            enabled_file_handlers: None,
+            client_mode: None,
+            launch_on_login: self.launch_on_login,
+            launch_on_browser: self.launch_on_browser,
+            arguments: self.arguments.clone(),
+            variables: self.variables.clone(),
+            update_manifest: true,
+            update_icons: true,
+            system_integration: self.system_integration,
+            accept_manifest_changes: false,
+            quiet: true,
+            store_client_config: self.store_client_config,
+            client: self.client.clone(),
+        };
+        command._run()?;
+
+        if self.launch_now {
+            self.launch(id)?;
+        }
+
+        Ok(id)
+    }
+
+    fn launch(&self, id: Ulid) -> Result<()> {
+        let command = SiteLaunchCommand {
+            id: id.into(),
+            url: vec![],
+            protocol: None,
+            file: vec![],
+            share: None,
+            arguments: vec![],
+            #[cfg(platform_macos)]
+            direct_launch: false,
+        };
+        command.run()
+    }
 }
 
 impl Run for SiteUninstallCommand {
     fn run(&self) -> Result<()> {
         let dirs = ProjectDirs::new()?;
-        let mut storage = Storage::load(&dirs)?;
+        let _lock = Storage::lock(&dirs)?;
+        let id = self.id.resolve(&dirs)?;
+        let mut storage = Storage::load_site(&dirs, id)?;
 
-        let site = storage.sites.get(&self.id).context("Web app does not exist")?;
+        let site = storage.sites.get(&id).context("Web app does not exist")?;
 
         if !self.quiet {
             warn!("This will remove the web app");
@@ -271,8 +612,8 @@ impl Run for SiteUninstallCommand {
             .get_mut(&site.profile)
             .context("Web app with invalid profile")?
             .sites
-            .retain(|id| *id != self.id);
-        let site = storage.sites.remove(&self.id);
+            .retain(|site| *site != id);
+        let site = storage.sites.remove(&id);
 
         if self.system_integration
             && let Some(site) = site
@@ -289,16 +630,165 @@ impl Run for SiteUninstallCommand {
     }
 }
 
-impl Run for SiteUpdateCommand {
+impl Run for SiteMoveCommand {
     fn run(&self) -> Result<()> {
         let dirs = ProjectDirs::new()?;
+        let _lock = Storage::lock(&dirs)?;
         let mut storage = Storage::load(&dirs)?;
+        let id = self.id.resolve_in(&storage)?;
+
+        let site = storage.sites.get(&id).context("Web app does not exist")?;
+        let source = site.profile;
+
+        if !storage.profiles.contains_key(&self.profile) {
+            bail!("Profile does not exist");
+        }
+        if source == self.profile {
+            bail!("Web app is already in this profile");
+        }
+        if let Some(existing) = find_installed(&storage, site, self.profile) {
+            bail!("Web app is already installed in this profile: {existing}");
+        }
+
+        // The runtime keeps using the old profile, and locks its data while running
+        let registry = InstanceRegistry::new(&dirs);
+        if registry.get(id).is_some() {
+            bail!("Web app is running, stop it first");
+        }
+        if self.migrate_data
+            && registry
+                .list()
+                .iter()
+                .any(|instance| instance.profile == source || instance.profile == self.profile)
+        {
+            bail!("Web apps in the old or new profile are running, stop them first");
+        }
+
+        if self.migrate_data {
+            info!("Moving the web app data");
+            let origin = site.identity().origin();
+            let old = storage.profiles.get(&source).context("Web app with invalid profile")?;
+            let moved = old.move_origin_data(&dirs, &origin, &storage.profiles[&self.profile])?;
+            info!("Moved {moved} storage directories");
+        }
+
+        info!("Moving the web app");
+        storage
+            .profiles
+            .get_mut(&source)
+            .context("Web app with invalid profile")?
+            .sites
+            .retain(|site| *site != id);
+        storage.profiles.get_mut(&self.profile).context("Profile does not exist")?.sites.push(id);
+        storage.sites.get_mut(&id).context("Web app does not exist")?.profile = self.profile;
+        storage.write(&dirs)?;
+
+        info!("Web app moved!");
+        Ok(())
+    }
+}
+
+impl Run for SiteCopyCommand {
+    fn run(&self) -> Result<()> {
+        self._run()?;
+        Ok(())
+    }
+}
+
+impl SiteCopyCommand {
+    pub fn _run(&self) -> Result<Ulid> {
+        let dirs = ProjectDirs::new()?;
+        let _lock = Storage::lock(&dirs)?;
+        let mut storage = Storage::load(&dirs)?;
+        let id = self.id.resolve_in(&storage)?;
+
+        let site = storage.sites.get(&id).context("Web app does not exist")?;
+        let client = self.client.construct(site.config.client.as_ref())?;
+
+        if !storage.profiles.contains_key(&self.profile) {
+            bail!("Profile does not exist");
+        }
+        if let Some(existing) = find_installed(&storage, site, self.profile) {
+            bail!("Web app is already installed in this profile: {existing}");
+        }
+
+        info!("Copying the web app");
+
+        // Keep all settings and the manifest, so the copy does not need to be downloaded again
+        let mut site = site.clone();
+        site.ulid = Ulid::new();
+        site.profile = self.profile;
+        let ulid = site.ulid;
+
+        if site.config.alias.take().is_some() {
+            warn!("Web app aliases must be unique, so the copy does not have an alias");
+        }
+
+        if self.system_integration {
+            info!("Installing system integration");
+            integrations::install(&IntegrationInstallArgs {
+                site: &site,
+                dirs: &dirs,
+                client: Some(&client),
+                update_manifest: true,
+                update_icons: true,
+                old_name: None,
+            })
+            .context("Failed to install system integration")?;
+        }
 
-        let site = storage.sites.get_mut(&self.id).context("Web app does not exist")?;
+        storage.profiles.get_mut(&self.profile).context("Profile does not exist")?.sites.push(ulid);
+        storage.sites.insert(ulid, site);
+        storage.write(&dirs)?;
+
+        info!("Web app copied: {ulid}");
+        Ok(ulid)
+    }
+}
+
+impl Run for SiteUpdateCommand {
+    fn run(&self) -> Result<()> {
+        #[cfg(platform_linux)]
+        if let Some(schedule) = self.schedule {
+            return self.schedule(schedule);
+        }
+
+        match self.all {
+            true => self._run_all().map(|_| ()),
+            false => self._run().map(|_| ()),
+        }
+    }
+}
+
+impl SiteUpdateCommand {
+    pub fn _run(&self) -> Result<ManifestDiff> {
+        let dirs = ProjectDirs::new()?;
+        let _lock = Storage::lock(&dirs)?;
+        let selector = self.id.as_ref().context("Web app is not specified")?;
+
+        let alias =
+            self.alias.as_ref().map(|alias| alias.clone().filter(|alias| !alias.is_empty()));
+
+        // Queries and aliases need all web apps, so they are loaded once for both
+        // Updates do not change profiles, so they never need to be loaded
+        let (id, mut storage) = match (selector, &alias) {
+            (SiteSelector::Id(id), None | Some(None)) => (*id, Storage::load_site(&dirs, *id)?),
+            _ => {
+                let storage = Storage::load_sites(&dirs)?;
+                (selector.resolve_in(&storage)?, storage)
+            }
+        };
+
+        if let Some(Some(alias)) = &alias {
+            check_alias(&storage, id, alias)?;
+        }
+
+        let site = storage.sites.get_mut(&id).context("Web app does not exist")?;
         let old_name = site.name();
 
         info!("Updating the web app");
         store_value!(site.config.name, self.name);
+        store_value!(site.config.alias, alias);
         store_value!(site.config.description, self.description);
         store_value!(site.config.start_url, self.start_url);
         store_value!(site.config.icon_url, self.icon_url);
@@ -306,19 +796,43 @@ impl Run for SiteUpdateCommand {
         store_value_vec!(site.config.keywords, self.keywords);
         store_value!(site.config.enabled_url_handlers, self.enabled_url_handlers);
         store_value!(site.config.enabled_protocol_handlers, self.enabled_protocol_handlers);
+        store_value!(site.config.enabled_file_handlers, self.enabled_file_handlers);
+        if let Some(client_mode) = &self.client_mode {
+            let client_mode = client_mode.as_deref().map(str::parse).transpose();
+            site.config.client_mode = client_mode.map_err(anyhow::Error::msg)?;
+        }
         store_value!(site.config.launch_on_login, self.launch_on_login);
         store_value!(site.config.launch_on_browser, self.launch_on_browser);
 
-        let client = construct_certificates_and_client(
-            self.client.user_agent.as_deref(),
-            &self.client.tls_root_certificates_der,
-            &self.client.tls_root_certificates_pem,
-            self.client.tls_danger_accept_invalid_certs,
-            self.client.tls_danger_accept_invalid_hostnames,
-        )?;
+        if let Some(arguments) = &self.arguments {
+            site.config.arguments =
+                arguments.iter().filter(|argument| !argument.is_empty()).cloned().collect();
+        }
+        if let Some(variables) = &self.variables {
+            site.config.variables = parse_variables(variables)?;
+        }
+
+        if self.store_client_config {
+            site.config.client = self.client.to_stored()?;
+        }
+
+        let client = self.client.construct(site.config.client.as_ref())?;
 
+        let mut diff = ManifestDiff::default();
         if self.update_manifest {
-            site.update(&client).context("Failed to update web app manifest")?;
+            diff = site
+                .update(&client, |diff| self.approve_manifest_changes(diff))
+                .context("Failed to update web app manifest")?;
+
+            if diff.applied {
+                for change in &diff.changes {
+                    info!("{change}");
+                }
+            } else if diff.requires_approval() {
+                warn!("Manifest changes were not accepted, keeping the current manifest");
+            }
+
+            site.mark_updated();
         }
 
         if self.system_integration {
@@ -337,6 +851,293 @@ impl Run for SiteUpdateCommand {
         storage.write(&dirs)?;
 
         info!("Web app updated!");
+        Ok(diff)
+    }
+
+    /// Updates all web apps.
+    ///
+    /// Manifests and icons are checked with conditional HTTP requests, and
+    /// system integration is only updated for web apps whose manifest or icons
+    /// changed. Same as for single updates, HTTP client options of the command
+    /// take precedence over the configuration stored for each web app. Returns
+    /// changes of manifests of updated web apps.
+    pub fn _run_all(&self) -> Result<BTreeMap<Ulid, ManifestDiff>> {
+        let dirs = ProjectDirs::new()?;
+
+        // Web apps are updated without holding the lock, so other commands and
+        // the extension are not blocked until all manifests and icons are downloaded
+        let storage = Storage::load_sites(&dirs)?;
+
+        let mut diffs = BTreeMap::new();
+        let mut updated = vec![];
+        let mut failed = vec![];
+
+        for mut site in storage.sites.into_values() {
+            if let Some(age) = self.if_older_than
+                && !site.is_older_than(age)
+            {
+                info!("Web app {} was updated recently", site.ulid);
+                continue;
+            }
+
+            info!("Updating web app {}", site.ulid);
+            match self.update_site(&mut site, &dirs) {
+                Ok(diff) => {
+                    if !diff.is_empty() {
+                        diffs.insert(site.ulid, diff);
+                    }
+                    updated.push(site);
+                }
+                Err(error) => {
+                    error!("Failed to update web app {}: {error:#}", site.ulid);
+                    failed.push(site.ulid.to_string());
+                }
+            }
+        }
+
+        // Only the updated members are stored, so other changes made in the meantime are kept
+        let _lock = Storage::lock(&dirs)?;
+        let mut storage = Storage::load_sites(&dirs)?;
+
+        for site in updated {
+            let Some(current) = storage.sites.get_mut(&site.ulid) else {
+                warn!("Web app {} was uninstalled while updating it", site.ulid);
+                continue;
+            };
+
+            current.manifest = site.manifest;
+            current.extras = site.extras;
+            current.validators = site.validators;
+            current.last_updated = site.last_updated;
+        }
+
+        storage.write(&dirs)?;
+
+        if !failed.is_empty() {
+            bail!("Failed to update web apps: {}", failed.join(", "));
+        }
+
+        info!("All web apps updated!");
+        Ok(diffs)
+    }
+
+    /// Updates a single web app as a part of updating all web apps.
+    fn update_site(&self, site: &mut Site, dirs: &ProjectDirs) -> Result<ManifestDiff> {
+        let old_name = site.name();
+
+        let client = self.client.construct(site.config.client.as_ref())?;
+
+        // Without manifest and icon updates, integration is always re-applied
+        let mut modified = !self.update_manifest && !self.update_icons;
+        let mut diff = ManifestDiff::default();
+
+        if self.update_manifest {
+            diff = site
+                .update(&client, |diff| self.approve_manifest_changes(diff))
+                .context("Failed to update web app manifest")?;
+
+            if diff.applied {
+                for change in &diff.changes {
+                    info!("{change}");
+                }
+            } else if diff.requires_approval() {
+                warn!("Manifest changes were not accepted, keeping the current manifest");
+            }
+
+            modified |= diff.modified;
+        }
+
+        // Integration only needs to be updated if the manifest or icons changed
+        let mut icons = None;
+        if self.update_icons {
+            let (icons_modified, validators) = site.check_icons(&client);
+            modified |= icons_modified;
+            icons = Some(validators);
+        }
+
+        if !modified {
+            info!("Web app {} has not changed", site.ulid);
+        } else if self.system_integration {
+            integrations::install(&IntegrationInstallArgs {
+                site,
+                dirs,
+                client: Some(&client),
+                update_manifest: self.update_manifest,
+                update_icons: self.update_icons,
+                old_name: Some(&old_name),
+            })
+            .context("Failed to update system integration")?;
+
+            if let Some(validators) = icons {
+                site.record_icon_validators(validators);
+            }
+        }
+
+        if self.update_manifest {
+            site.mark_updated();
+        }
+
+        Ok(diff)
+    }
+
+    /// Installs or removes the background job that runs this update periodically.
+    #[cfg(platform_linux)]
+    fn schedule(&self, schedule: UpdateSchedule) -> Result<()> {
+        let dirs = ProjectDirs::new()?;
+
+        // Web apps cannot be approved in the background, so prompts are always disabled
+        let mut arguments: Vec<String> =
+            ["site", "update", "--all", "--quiet"].map(String::from).into();
+        if let Some(age) = self.if_older_than {
+            arguments.extend(["--if-older-than".into(), format!("{}s", age.as_secs())]);
+        }
+        let flags = [
+            (self.accept_manifest_changes, "--accept-manifest-changes"),
+            (!self.update_manifest, "--no-manifest-updates"),
+            (!self.update_icons, "--no-icon-updates"),
+            (!self.system_integration, "--no-system-integration"),
+        ];
+        arguments.extend(flags.into_iter().filter(|(set, _)| *set).map(|(_, flag)| flag.into()));
+        arguments.extend(self.client.to_arguments()?);
+
+        integrations::schedule_updates(&dirs, schedule, &arguments)?;
+
+        match schedule {
+            UpdateSchedule::None => info!("Background updates removed!"),
+            _ => info!("Background updates scheduled!"),
+        }
+
+        Ok(())
+    }
+
+    fn approve_manifest_changes(&self, diff: &ManifestDiff) -> Result<bool> {
+        if self.accept_manifest_changes {
+            return Ok(true);
+        }
+
+        warn!("The updated manifest contains changes that require approval");
+        for change in &diff.changes {
+            warn!("{change}");
+        }
+
+        if self.quiet {
+            return Ok(false);
+        }
+
+        print!("Do you want to accept these changes (y/n)? ");
+        io::stdout().flush()?;
+
+        let mut confirm = String::new();
+        io::stdin().read_line(&mut confirm)?;
+        confirm = confirm.trim().into();
+
+        Ok(confirm == "Y" || confirm == "y")
+    }
+}
+
+impl Run for SitePsCommand {
+    fn run(&self) -> Result<()> {
+        let dirs = ProjectDirs::new()?;
+        let storage = Storage::load_sites(&dirs)?;
+        let instances = InstanceRegistry::new(&dirs).list();
+
+        if instances.is_empty() {
+            info!("No web apps are running");
+            return Ok(());
+        }
+
+        for instance in instances {
+            let name = match storage.sites.get(&instance.site) {
+                Some(site) => site.name(),
+                None => "* Uninstalled *".into(),
+            };
+
+            println!(
+                "- {name} ({}): PID {}, profile {}, started {}",
+                instance.site,
+                instance.pid,
+                instance.profile,
+                format_time(UNIX_EPOCH + Duration::from_secs(instance.started))
+            );
+        }
+
+        Ok(())
+    }
+}
+
+impl Run for SiteKillCommand {
+    fn run(&self) -> Result<()> {
+        self._run()?;
+        Ok(())
+    }
+}
+
+impl SiteKillCommand {
+    pub fn _run(&self) -> Result<Vec<Ulid>> {
+        let dirs = ProjectDirs::new()?;
+        let id = self.id.resolve(&dirs)?;
+
+        let registry = InstanceRegistry::new(&dirs);
+        let instance = registry.get(id).context("Web app is not running")?;
+
+        // All web apps shown in the same runtime process are closed together
+        let closed: Vec<Ulid> = registry
+            .list()
+            .into_iter()
+            .filter(|other| other.pid == instance.pid)
+            .map(|other| other.site)
+            .collect();
+
+        if closed.len() > 1 {
+            let others: Vec<String> =
+                closed.iter().filter(|site| **site != id).map(Ulid::to_string).collect();
+            warn!("This will also close other web apps in the same runtime: {}", others.join(", "));
+        }
+
+        info!("Stopping the web app");
+        instance.stop(self.force)?;
+
+        for site in &closed {
+            registry.remove(*site);
+        }
+
+        info!("Web app stopped!");
+        Ok(closed)
+    }
+}
+
+impl Run for SiteLintCommand {
+    fn run(&self) -> Result<()> {
+        // Informational messages are printed to stdout, so they would break the JSON output
+        if self.json {
+            log::set_max_level(log::LevelFilter::Warn);
+        }
+
+        let client = self.client.construct(None)?;
+
+        // Failures to find the manifest are reported the same as failures to download it
+        let report = match resolve_urls(&self.manifest_url, self.document_url.as_ref(), &client) {
+            Ok((manifest_url, document_url)) => lint(&manifest_url, &document_url, &client),
+            Err(error) => {
+                let document_url = self.document_url.as_ref().unwrap_or(&self.manifest_url);
+                LintReport::failed(&self.manifest_url, document_url, &error)
+            }
+        };
+
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        } else if report.findings.is_empty() {
+            info!("No problems found");
+        } else {
+            for finding in &report.findings {
+                println!("- {finding}");
+            }
+        }
+
+        if report.has_errors() {
+            bail!("The web app manifest has errors");
+        }
+
         Ok(())
     }
 }
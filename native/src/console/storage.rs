@@ -0,0 +1,83 @@
+use std::io;
+use std::io::Write;
+
+use anyhow::Result;
+use log::{info, warn};
+
+use crate::console::Run;
+use crate::console::app::{HistoryCommand, StorageBackupsCommand, StorageRestoreCommand};
+use crate::directories::ProjectDirs;
+use crate::storage::{JournalTarget, Storage};
+use crate::utils::format_time;
+
+impl Run for StorageBackupsCommand {
+    fn run(&self) -> Result<()> {
+        let dirs = ProjectDirs::new()?;
+        let backups = Storage::backups(&dirs)?;
+
+        if backups.is_empty() {
+            info!("No storage backups available");
+            return Ok(());
+        }
+
+        for backup in backups {
+            println!("- {}: {} ({} bytes)", backup.id, format_time(backup.created()), backup.size);
+        }
+
+        Ok(())
+    }
+}
+
+impl Run for StorageRestoreCommand {
+    fn run(&self) -> Result<()> {
+        let dirs = ProjectDirs::new()?;
+
+        if !self.quiet {
+            warn!("This will replace all web apps, profiles and config with the backup");
+            warn!("System integration and profile data will NOT be changed");
+
+            print!("Do you want to continue (y/n)? ");
+            io::stdout().flush()?;
+
+            let mut confirm = String::new();
+            io::stdin().read_line(&mut confirm)?;
+            confirm = confirm.trim().into();
+
+            if confirm != "Y" && confirm != "y" {
+                info!("Aborting!");
+                return Ok(());
+            }
+        }
+
+        Storage::restore(&dirs, &self.backup)
+    }
+}
+
+impl Run for HistoryCommand {
+    fn run(&self) -> Result<()> {
+        let dirs = ProjectDirs::new()?;
+        let mut history = Storage::history(&dirs)?;
+
+        if let Some(id) = self.id {
+            history.retain(|entry| match entry.target {
+                JournalTarget::Site(target) | JournalTarget::Profile(target) => target == id,
+                _ => false,
+            });
+        }
+
+        if let Some(limit) = self.limit {
+            history.drain(..history.len().saturating_sub(limit));
+        }
+
+        if history.is_empty() {
+            info!("No changes recorded");
+            return Ok(());
+        }
+
+        for entry in history {
+            println!("- {} [{}]: {entry}", format_time(entry.created()), entry.source);
+        }
+
+        Ok(())
+    }
+}
@@ -1,20 +1,26 @@
+use std::collections::BTreeSet;
 use std::convert::TryInto;
 use std::fmt::Write as FmtWrite;
 use std::fs::{File, copy, create_dir_all, remove_file, write};
 use std::io::Write as IoWrite;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, bail};
 use glob::glob;
 use image::GenericImageView;
 use log::{debug, error, warn};
 use reqwest::blocking::Client;
+use ulid::Ulid;
 use url::Url;
 use web_app_manifest::resources::IconResource;
 use web_app_manifest::types::{ImagePurpose, ImageSize};
 
 use crate::components::site::Site;
+#[cfg(platform_linux)]
+use crate::console::app::UpdateSchedule;
+#[cfg(platform_linux)]
+use crate::directories::ProjectDirs;
 use crate::integrations::categories::XDG_CATEGORIES;
 use crate::integrations::utils::{download_icon, normalize_category_name, store_icon};
 use crate::integrations::{IntegrationInstallArgs, IntegrationUninstallArgs};
@@ -32,6 +38,8 @@ const CREATE_ICON_FILE_ERROR: &str = "Failed to create icon file";
 const CREATE_APPLICATION_DIRECTORY_ERROR: &str = "Failed to create application directory";
 const WRITE_APPLICATION_FILE_ERROR: &str = "Failed to write application file";
 const COPY_STARTUP_ENTRY_ERROR: &str = "Failed to copy startup entry";
+const CREATE_MIME_DIRECTORY_ERROR: &str = "Failed to create MIME package directory";
+const WRITE_MIME_FILE_ERROR: &str = "Failed to write MIME package file";
 
 //////////////////////////////
 // Utils
@@ -232,7 +240,7 @@ Categories=GTK;{categories};
 Icon={icon}
 Exec={exe} site launch {id} --protocol %u
 Actions={actions}
-MimeType={protocols}
+MimeType={protocols}{files}
 Terminal=false
 StartupNotify=true
 StartupWMClass={wmclass}
@@ -242,10 +250,13 @@ StartupWMClass={wmclass}
         description = &ids.description,
         keywords = &args.site.keywords().join(";"),
         categories = &categories.join(";"),
-        actions = (0..args.site.manifest.shortcuts.len()).fold(String::new(), |mut output, i| {
-            let _ = write!(output, "{i};");
-            output
-        }),
+        actions = (0..args.site.manifest.shortcuts.len()).fold(
+            if args.site.share_target().is_some() { "share;".into() } else { String::new() },
+            |mut output, i| {
+                let _ = write!(output, "{i};");
+                output
+            }
+        ),
         protocols = args.site.config.enabled_protocol_handlers.iter().fold(
             String::new(),
             |mut output, protocol| {
@@ -253,6 +264,10 @@ StartupWMClass={wmclass}
                 output
             }
         ),
+        files = args.site.file_types().keys().fold(String::new(), |mut output, mime| {
+            let _ = write!(output, "{mime};");
+            output
+        }),
         icon = &ids.classid,
         wmclass = &ids.classid,
         exe = &exe,
@@ -287,6 +302,27 @@ Exec={exe} site launch {siteid} --url \"{url}\"
         entry += &action;
     }
 
+    // Store the share action, so other programs can share URLs or files with the web app
+    if let Some(target) = args.site.share_target() {
+        let parameter = if target.params.files.is_empty() { "--url %u" } else { "--file %F" };
+
+        let action = format!(
+            "
+[Desktop Action share]
+Name=Share with {name}
+Icon={icon}
+Exec={exe} site share {siteid} {parameter}
+",
+            siteid = &ids.ulid,
+            name = &ids.name,
+            icon = &ids.classid,
+            parameter = &parameter,
+            exe = &exe,
+        );
+
+        entry += &action;
+    }
+
     // Create the directory and write the file
     create_dir_all(directory).context(CREATE_APPLICATION_DIRECTORY_ERROR)?;
     write(filename, entry).context(WRITE_APPLICATION_FILE_ERROR)?;
@@ -294,6 +330,44 @@ Exec={exe} site launch {siteid} --url \"{url}\"
     Ok(())
 }
 
+/// Register file types of enabled file handlers.
+///
+/// The types are stored as a shared MIME-info package, so files can be
+/// recognized by their extensions even when the system does not know the
+/// types yet. The package is removed when no file handlers are enabled.
+fn create_mime_package(args: &IntegrationInstallArgs, ids: &SiteIds, data: &Path) -> Result<()> {
+    let directory = data.join("mime/packages");
+    let filename = directory.join(format!("{}.xml", ids.classid));
+
+    let types = args.site.file_types();
+    if types.is_empty() {
+        let _ = remove_file(filename);
+        return Ok(());
+    }
+
+    let mut package = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<mime-info xmlns=\"http://www.freedesktop.org/standards/shared-mime-info\">
+",
+    );
+
+    // MIME types and extensions are validated when parsing the manifest, so they can be written directly
+    for (mime, extensions) in types {
+        let _ = writeln!(package, "  <mime-type type=\"{mime}\">");
+        for extension in extensions {
+            let _ = writeln!(package, "    <glob pattern=\"*{extension}\"/>");
+        }
+        let _ = writeln!(package, "  </mime-type>");
+    }
+
+    package += "</mime-info>\n";
+
+    create_dir_all(directory).context(CREATE_MIME_DIRECTORY_ERROR)?;
+    write(filename, package).context(WRITE_MIME_FILE_ERROR)?;
+
+    Ok(())
+}
+
 fn create_startup_entry(
     args: &IntegrationInstallArgs,
     ids: &SiteIds,
@@ -320,12 +394,154 @@ fn remove_desktop_entry(classid: &str, data: &Path) {
     let _ = remove_file(filename);
 }
 
+fn remove_mime_package(classid: &str, data: &Path) {
+    let directory = data.join("mime/packages");
+    let filename = directory.join(format!("{classid}.xml"));
+    let _ = remove_file(filename);
+}
+
 fn remove_startup_entry(classid: &str, config: &Path) {
     let directory = config.join("autostart");
     let filename = directory.join(format!("{classid}.desktop"));
     let _ = remove_file(filename);
 }
 
+//////////////////////////////
+// Background Updates
+//////////////////////////////
+
+#[cfg(platform_linux)]
+const UPDATE_JOB_NAME: &str = "firefoxpwa-update";
+
+#[cfg(platform_linux)]
+fn systemctl(arguments: &[&str]) -> Result<()> {
+    let status = Command::new("systemctl").arg("--user").args(arguments).status()?;
+    if !status.success() {
+        bail!("systemctl exited with {status}");
+    }
+    Ok(())
+}
+
+/// Quotes the command for the `ExecStart` key of systemd units.
+///
+/// See: https://www.freedesktop.org/software/systemd/man/latest/systemd.service.html#Command%20lines
+#[cfg(platform_linux)]
+fn quote_systemd_command(command: &[String]) -> String {
+    let quote = |argument: &String| {
+        let mut quoted = String::from('"');
+        for char in argument.chars() {
+            match char {
+                '\\' | '"' => quoted.extend(['\\', char]),
+                '%' => quoted.push_str("%%"),
+                '$' => quoted.push_str("$$"),
+                _ => quoted.push(char),
+            }
+        }
+        quoted.push('"');
+        quoted
+    };
+
+    command.iter().map(quote).collect::<Vec<_>>().join(" ")
+}
+
+/// Quotes the command for the `Exec` key of desktop entries.
+///
+/// Reserved characters are escaped inside the quoted arguments, and then escaped
+/// again, because the `Exec` key is a string value with its own escape sequences.
+///
+/// See: https://specifications.freedesktop.org/desktop-entry-spec/latest/exec-variables.html
+#[cfg(platform_linux)]
+fn quote_desktop_command(command: &[String]) -> String {
+    let quote = |argument: &String| {
+        let mut quoted = String::from('"');
+        for char in argument.chars() {
+            match char {
+                '"' | '`' | '$' => quoted.extend(['\\', '\\', char]),
+                '\\' => quoted.push_str("\\\\\\\\"),
+                '%' => quoted.push_str("%%"),
+                _ => quoted.push(char),
+            }
+        }
+        quoted.push('"');
+        quoted
+    };
+
+    command.iter().map(quote).collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(platform_linux)]
+fn create_update_timer(command: &[String], config: &Path) -> Result<()> {
+    let directory = config.join("systemd/user");
+    let command = quote_systemd_command(command);
+    let service = format!(
+        "[Unit]
+Description=Update Progressive Web Apps for Firefox
+
+[Service]
+Type=oneshot
+ExecStart={command}
+"
+    );
+    let timer = "[Unit]
+Description=Periodically update Progressive Web Apps for Firefox
+
+[Timer]
+OnStartupSec=5min
+OnUnitActiveSec=1h
+RandomizedDelaySec=5min
+
+[Install]
+WantedBy=timers.target
+";
+
+    create_dir_all(&directory).context("Failed to create systemd unit directory")?;
+    write(directory.join(format!("{UPDATE_JOB_NAME}.service")), service)
+        .context("Failed to write systemd service")?;
+    write(directory.join(format!("{UPDATE_JOB_NAME}.timer")), timer)
+        .context("Failed to write systemd timer")?;
+
+    systemctl(&["daemon-reload"]).context("Failed to reload systemd units")?;
+    systemctl(&["enable", "--now", &format!("{UPDATE_JOB_NAME}.timer")])
+        .context("Failed to enable systemd timer")?;
+
+    Ok(())
+}
+
+#[cfg(platform_linux)]
+fn create_update_autostart_entry(command: &[String], config: &Path) -> Result<()> {
+    let directory = config.join("autostart");
+    let command = quote_desktop_command(command);
+    let entry = format!(
+        "[Desktop Entry]
+Type=Application
+Version=1.4
+Name=Update Progressive Web Apps for Firefox
+Exec={command}
+NoDisplay=true
+X-GNOME-Autostart-Delay=60
+"
+    );
+
+    create_dir_all(&directory).context("Failed to create autostart directory")?;
+    write(directory.join(format!("{UPDATE_JOB_NAME}.desktop")), entry)
+        .context("Failed to write autostart entry")?;
+
+    Ok(())
+}
+
+#[cfg(platform_linux)]
+fn remove_update_jobs(config: &Path) {
+    let timer = config.join(format!("systemd/user/{UPDATE_JOB_NAME}.timer"));
+    if timer.exists() {
+        let _ = systemctl(&["disable", "--now", &format!("{UPDATE_JOB_NAME}.timer")]);
+        let _ = remove_file(timer);
+        let _ = remove_file(config.join(format!("systemd/user/{UPDATE_JOB_NAME}.service")));
+        let _ = systemctl(&["daemon-reload"]);
+    }
+
+    let _ = remove_file(config.join(format!("autostart/{UPDATE_JOB_NAME}.desktop")));
+}
+
 //////////////////////////////
 // Interface
 //////////////////////////////
@@ -345,6 +561,7 @@ pub fn install(args: &IntegrationInstallArgs) -> Result<()> {
     }
 
     create_desktop_entry(args, &ids, &exe, &data).context("Failed to create application entry")?;
+    create_mime_package(args, &ids, &data).context("Failed to register file types")?;
     create_startup_entry(args, &ids, &data, &config).context("Failed to create startup entry")?;
     update_application_cache(&data);
 
@@ -361,8 +578,116 @@ pub fn uninstall(args: &IntegrationUninstallArgs) -> Result<()> {
 
     remove_icons(&ids.classid, data);
     remove_desktop_entry(&ids.classid, data);
+    remove_mime_package(&ids.classid, data);
     remove_startup_entry(&ids.classid, config);
     update_application_cache(data);
 
     Ok(())
 }
+
+/// Find application entries and icons of web apps that are not installed.
+///
+/// Such files usually remain when the storage is changed without
+/// uninstalling the system integration of removed web apps.
+pub fn find_orphans(sites: &BTreeSet<Ulid>) -> Result<Vec<PathBuf>> {
+    let base = directories::BaseDirs::new().context(BASE_DIRECTORIES_ERROR)?;
+    let data = base.data_dir().display().to_string();
+    let config = base.config_dir().display().to_string();
+
+    let patterns = [
+        format!("{data}/applications/FFPWA-*.desktop"),
+        format!("{config}/autostart/FFPWA-*.desktop"),
+        format!("{data}/icons/hicolor/*/apps/FFPWA-*"),
+        format!("{data}/mime/packages/FFPWA-*.xml"),
+    ];
+
+    let mut orphans = vec![];
+
+    for pattern in patterns {
+        for path in glob(&pattern).context("Failed to search for orphaned files")?.flatten() {
+            // File names start with the class ID, optionally followed by a suffix
+            let ulid = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_prefix("FFPWA-"))
+                .and_then(|name| name.get(..26))
+                .and_then(|ulid| Ulid::from_string(ulid).ok());
+
+            if let Some(ulid) = ulid
+                && !sites.contains(&ulid)
+            {
+                orphans.push(path);
+            }
+        }
+    }
+
+    Ok(orphans)
+}
+
+/// Remove orphaned files found by [`find_orphans`].
+pub fn remove_orphans(paths: &[PathBuf]) -> Result<()> {
+    let base = directories::BaseDirs::new().context(BASE_DIRECTORIES_ERROR)?;
+
+    for path in paths {
+        remove_file(path).with_context(|| format!("Failed to remove {}", path.display()))?;
+    }
+
+    update_application_cache(base.data_dir());
+    Ok(())
+}
+
+/// Run updates of all web apps periodically in the background.
+///
+/// Both a systemd user timer and an XDG autostart entry run the update shortly
+/// after login, and the timer also runs it every hour. Web apps that were updated
+/// recently are skipped by the update itself. The previous schedule is always
+/// removed, so only one of them is active at a time.
+#[cfg(platform_linux)]
+pub fn schedule_updates(
+    dirs: &ProjectDirs,
+    schedule: UpdateSchedule,
+    arguments: &[String],
+) -> Result<()> {
+    let exe = dirs.executables.join("firefoxpwa").display().to_string();
+    let command: Vec<String> = [exe].into_iter().chain(arguments.iter().cloned()).collect();
+
+    let base = directories::BaseDirs::new().context(BASE_DIRECTORIES_ERROR)?;
+    let config = base.config_dir().to_owned();
+
+    remove_update_jobs(&config);
+
+    match schedule {
+        UpdateSchedule::Systemd => create_update_timer(&command, &config)
+            .context("Failed to create background update timer"),
+        UpdateSchedule::Autostart => create_update_autostart_entry(&command, &config)
+            .context("Failed to create background update entry"),
+        UpdateSchedule::None => Ok(()),
+    }
+}
+
+#[cfg(all(test, platform_linux))]
+mod tests {
+    use super::*;
+
+    fn command() -> Vec<String> {
+        ["/opt/firefox pwa/firefoxpwa", "say \"hi\"", "100%", "$HOME", "`id`", "back\\slash"]
+            .map(String::from)
+            .into()
+    }
+
+    #[test]
+    fn quotes_systemd_command() {
+        assert_eq!(
+            quote_systemd_command(&command()),
+            r#""/opt/firefox pwa/firefoxpwa" "say \"hi\"" "100%%" "$$HOME" "`id`" "back\\slash""#
+        );
+    }
+
+    #[test]
+    fn quotes_desktop_command() {
+        assert_eq!(
+            quote_desktop_command(&command()),
+            r#""/opt/firefox pwa/firefoxpwa" "say \\"hi\\"" "100%%" "\\$HOME" "\\`id\\`" "back\\\\slash""#
+        );
+    }
+}
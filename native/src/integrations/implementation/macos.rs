@@ -2,7 +2,7 @@ use std::cmp::Ordering;
 use std::convert::TryInto;
 use std::fs::{File, create_dir_all, remove_dir_all, rename, write};
 use std::io::{BufWriter, Read, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf, absolute};
 use std::process::{Child, Command, Stdio};
 
 use anyhow::{Context, Result, bail};
@@ -568,7 +568,13 @@ pub fn uninstall(args: &IntegrationUninstallArgs) -> Result<()> {
 }
 
 #[inline]
-pub fn launch(site: &Site, urls: &[Url], arguments: &[String]) -> Result<Child> {
+pub fn launch(
+    site: &Site,
+    urls: &[Url],
+    files: &[PathBuf],
+    share: Option<&Path>,
+    arguments: &[String],
+) -> Result<Child> {
     let name = site.name();
 
     let app_path = directories::BaseDirs::new()
@@ -585,7 +591,7 @@ pub fn launch(site: &Site, urls: &[Url], arguments: &[String]) -> Result<Child>
     let mut args = vec![app_path.display().to_string()];
 
     // We need to append `--args` when we provide additional arguments to the web app
-    if !urls.is_empty() || !arguments.is_empty() {
+    if !urls.is_empty() || !files.is_empty() || share.is_some() || !arguments.is_empty() {
         args.extend_from_slice(&["--args".into()]);
     }
 
@@ -594,6 +600,18 @@ pub fn launch(site: &Site, urls: &[Url], arguments: &[String]) -> Result<Child>
         args.extend_from_slice(&["--url".into(), url.to_string()]);
     }
 
+    // Support launching web apps with files
+    // Paths need to be absolute because the bundle is launched in a different directory
+    for file in files {
+        let file = absolute(file).context("Failed to resolve file path")?;
+        args.extend_from_slice(&["--file".into(), file.display().to_string()]);
+    }
+
+    // Support launching web apps with a share target request
+    if let Some(share) = share {
+        args.extend_from_slice(&["--share".into(), share.display().to_string()]);
+    }
+
     // Support launching web app with custom Firefox arguments
     if !arguments.is_empty() {
         args.extend_from_slice(&["--".into()]);
@@ -1,9 +1,16 @@
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
 use anyhow::Result;
 use cfg_if::cfg_if;
+use ulid::Ulid;
 
 #[rustfmt::skip]
 #[cfg(platform_macos)]
-use {crate::components::site::Site, std::process::Child, url::Url};
+use {crate::components::site::Site, std::path::Path, std::process::Child, url::Url};
+
+#[cfg(platform_linux)]
+use {crate::console::app::UpdateSchedule, crate::directories::ProjectDirs};
 
 use crate::integrations::{IntegrationInstallArgs, IntegrationUninstallArgs};
 
@@ -53,8 +60,48 @@ pub fn uninstall(args: &IntegrationUninstallArgs) -> Result<()> {
     }
 }
 
+#[inline]
+pub fn find_orphans(sites: &BTreeSet<Ulid>) -> Result<Vec<PathBuf>> {
+    cfg_if! {
+        if #[cfg(any(platform_linux, platform_bsd))] {
+            linux::find_orphans(sites)
+        } else {
+            let _ = sites;
+            Ok(vec![])
+        }
+    }
+}
+
+#[inline]
+pub fn remove_orphans(paths: &[PathBuf]) -> Result<()> {
+    cfg_if! {
+        if #[cfg(any(platform_linux, platform_bsd))] {
+            linux::remove_orphans(paths)
+        } else {
+            let _ = paths;
+            Ok(())
+        }
+    }
+}
+
 #[cfg(platform_macos)]
 #[inline]
-pub fn launch(site: &Site, urls: &[Url], arguments: &[String]) -> Result<Child> {
-    macos::launch(site, urls, arguments)
+pub fn launch(
+    site: &Site,
+    urls: &[Url],
+    files: &[PathBuf],
+    share: Option<&Path>,
+    arguments: &[String],
+) -> Result<Child> {
+    macos::launch(site, urls, files, share, arguments)
+}
+
+#[cfg(platform_linux)]
+#[inline]
+pub fn schedule_updates(
+    dirs: &ProjectDirs,
+    schedule: UpdateSchedule,
+    arguments: &[String],
+) -> Result<()> {
+    linux::schedule_updates(dirs, schedule, arguments)
 }
@@ -9,7 +9,9 @@ mod utils;
 
 #[cfg(platform_macos)]
 pub use implementation::launch;
-pub use implementation::{install, uninstall};
+#[cfg(platform_linux)]
+pub use implementation::schedule_updates;
+pub use implementation::{find_orphans, install, remove_orphans, uninstall};
 
 #[derive(Debug, Clone)]
 pub struct IntegrationInstallArgs<'a> {
@@ -14,6 +14,8 @@ use url::Url;
 use web_app_manifest::resources::IconResource;
 use web_app_manifest::types::{ImagePurpose, ImageSize, Url as ManifestUrl};
 
+use crate::storage::cached_fetch;
+
 //////////////////////////////
 // Public
 //////////////////////////////
@@ -51,18 +53,15 @@ pub fn normalize_category_name(category: &str) -> String {
 /// Download the icon from the URL.
 ///
 /// Icon can be downloaded from the network using the `reqwest` crate
-/// or decoded from a data URL. Once downloaded, the function returns
-/// the icon bytes and its content type.
+/// or decoded from a data URL. Network icons are cached and only downloaded
+/// again when they change. Once downloaded, the function returns the icon
+/// bytes and its content type.
 pub fn download_icon(url: Url, client: &Client) -> Result<(Vec<u8>, String)> {
-    // Download using `reqwest`
+    // Download using `reqwest` and the HTTP cache
     if url.scheme() != "data" {
-        let response = client.get(url).send()?;
-        let r#type = match response.headers().get(reqwest::header::CONTENT_TYPE) {
-            Some(r#type) => r#type.to_str()?.into(),
-            None => "application/octet-stream".into(),
-        };
-        let bytes = response.bytes()?.to_vec();
-        Ok((bytes, r#type))
+        let response = cached_fetch(client, client.get(url).build()?)?;
+        let r#type = response.content_type.unwrap_or_else(|| "application/octet-stream".into());
+        Ok((response.body, r#type))
 
     // Download using `data-url`
     } else {
@@ -0,0 +1,357 @@
+use std::cmp::Reverse;
+use std::fs::{
+    copy,
+    create_dir,
+    create_dir_all,
+    read_dir,
+    read_to_string,
+    remove_dir_all,
+    remove_file,
+};
+use std::io::ErrorKind;
+use std::iter::once;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result, bail};
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::directories::ProjectDirs;
+use crate::storage::Storage;
+use crate::storage::layout::{self, StorageScope};
+
+/// Number of storage backups that are kept before the oldest are removed.
+const BACKUPS_LIMIT: usize = 20;
+
+const BACKUP_CREATE_ERROR: &str = "Failed to create storage backup";
+const BACKUP_LIST_ERROR: &str = "Failed to list storage backups";
+const BACKUP_RESTORE_ERROR: &str = "Failed to restore storage backup";
+
+/// Copies of the storage files made before they were replaced.
+#[derive(Serialize, Debug, Eq, PartialEq, Clone)]
+pub struct StorageBackup {
+    /// A backup ID.
+    ///
+    /// Consists of the time when the backup was created, in milliseconds since
+    /// the Unix epoch, followed by a counter when multiple backups were created
+    /// in the same millisecond. Used to restore it.
+    pub id: String,
+
+    /// A time when the backup was created, in milliseconds since the Unix epoch.
+    pub timestamp: u64,
+
+    /// A size of the backup files in bytes.
+    pub size: u64,
+}
+
+impl StorageBackup {
+    /// Time when the backup was created.
+    #[inline]
+    pub fn created(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(self.timestamp)
+    }
+}
+
+#[inline]
+fn backups_directory(dirs: &ProjectDirs) -> PathBuf {
+    dirs.userdata.join("backups")
+}
+
+/// Parses a backup ID into its timestamp and counter.
+fn parse_backup_id(id: &str) -> Option<(u64, u64)> {
+    let (timestamp, counter) = id.split_once('-').unwrap_or((id, "0"));
+    let parse = |value: &str| match value.chars().all(|char| char.is_ascii_digit()) {
+        true => value.parse().ok(),
+        false => None,
+    };
+    Some((parse(timestamp)?, parse(counter)?))
+}
+
+fn backup_directory(dirs: &ProjectDirs, id: &str) -> Result<PathBuf> {
+    // Backup IDs only contain digits and a separator, so this also prevents path traversal
+    if parse_backup_id(id).is_none() {
+        bail!("Invalid backup ID: {id}");
+    }
+
+    Ok(backups_directory(dirs).join(id))
+}
+
+/// A backup made by older versions, which contains the whole storage in a single file.
+fn legacy_backup_filename(dirs: &ProjectDirs, id: &str) -> Result<PathBuf> {
+    if parse_backup_id(id).is_none() {
+        bail!("Invalid backup ID: {id}");
+    }
+
+    Ok(backups_directory(dirs).join(format!("config-{id}.json")))
+}
+
+/// Calculates the total size of files in the directory.
+fn directory_size(directory: &Path) -> Result<u64> {
+    let mut size = 0;
+
+    for entry in read_dir(directory)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+
+        size += match metadata.is_dir() {
+            true => directory_size(&entry.path())?,
+            false => metadata.len(),
+        };
+    }
+
+    Ok(size)
+}
+
+impl Storage {
+    /// Copies storage files that are about to be replaced into the backups directory.
+    ///
+    /// Each backup only contains files that were changed by a single write,
+    /// so the storage never has to be read completely. Only the newest backups
+    /// are kept. Does nothing if none of the files exist yet.
+    pub(super) fn backup(dirs: &ProjectDirs, files: &[PathBuf]) -> Result<()> {
+        let files: Vec<&PathBuf> =
+            files.iter().filter(|file| dirs.userdata.join(file).exists()).collect();
+
+        if files.is_empty() {
+            return Ok(());
+        }
+
+        let timestamp =
+            SystemTime::now().duration_since(UNIX_EPOCH).context(BACKUP_CREATE_ERROR)?.as_millis();
+
+        create_dir_all(backups_directory(dirs)).context(BACKUP_CREATE_ERROR)?;
+
+        // Multiple writes can happen in the same millisecond, so a counter
+        // is appended to the ID to prevent overwriting the previous backup
+        let mut counter = 0;
+        let directory = loop {
+            let id = match counter {
+                0 => timestamp.to_string(),
+                _ => format!("{timestamp}-{counter}"),
+            };
+
+            let directory = backup_directory(dirs, &id)?;
+            match create_dir(&directory) {
+                Ok(_) => break directory,
+                Err(error) if error.kind() == ErrorKind::AlreadyExists => counter += 1,
+                Err(error) => return Err(error).context(BACKUP_CREATE_ERROR),
+            }
+        };
+
+        for file in files {
+            let target = directory.join(file);
+            if let Some(parent) = target.parent() {
+                create_dir_all(parent).context(BACKUP_CREATE_ERROR)?;
+            }
+            copy(dirs.userdata.join(file), target).context(BACKUP_CREATE_ERROR)?;
+        }
+
+        for backup in Self::backups(dirs)?.into_iter().skip(BACKUPS_LIMIT) {
+            let _ = remove_dir_all(backup_directory(dirs, &backup.id)?);
+            let _ = remove_file(legacy_backup_filename(dirs, &backup.id)?);
+        }
+
+        Ok(())
+    }
+
+    /// Lists all available storage backups, from newest to oldest.
+    pub fn backups(dirs: &ProjectDirs) -> Result<Vec<StorageBackup>> {
+        let directory = backups_directory(dirs);
+        let mut backups = vec![];
+
+        if !directory.exists() {
+            return Ok(backups);
+        }
+
+        for entry in read_dir(directory).context(BACKUP_LIST_ERROR)? {
+            let entry = entry.context(BACKUP_LIST_ERROR)?;
+            let metadata = entry.metadata().context(BACKUP_LIST_ERROR)?;
+            let filename = entry.file_name();
+
+            let id = match metadata.is_dir() {
+                true => filename.to_str(),
+                false => filename
+                    .to_str()
+                    .and_then(|name| name.strip_prefix("config-"))
+                    .and_then(|name| name.strip_suffix(".json")),
+            };
+
+            let Some((id, (timestamp, counter))) =
+                id.and_then(|id| Some((id, parse_backup_id(id)?)))
+            else {
+                warn!("Ignoring unknown file in backups directory: {}", entry.path().display());
+                continue;
+            };
+
+            let size = match metadata.is_dir() {
+                true => directory_size(&entry.path()).context(BACKUP_LIST_ERROR)?,
+                false => metadata.len(),
+            };
+            backups.push((counter, StorageBackup { id: id.into(), timestamp, size }));
+        }
+
+        backups.sort_unstable_by_key(|(counter, backup)| Reverse((backup.timestamp, *counter)));
+        Ok(backups.into_iter().map(|(_, backup)| backup).collect())
+    }
+
+    /// Replaces the storage with the backup.
+    ///
+    /// Backups only contain files changed by a single write, so files that
+    /// are missing from the backup are taken from the newer backups or the
+    /// current storage, whichever has them as they were at the backup time.
+    /// The backup is validated before it is restored, and the current
+    /// storage is itself backed up, so restoring can also be undone.
+    pub fn restore(dirs: &ProjectDirs, id: &str) -> Result<()> {
+        let _lock = Self::lock(dirs)?;
+
+        let legacy = legacy_backup_filename(dirs, id)?;
+        let backups = Self::backups(dirs)?;
+        let Some(position) = backups.iter().position(|backup| backup.id == id) else {
+            bail!("Backup does not exist");
+        };
+
+        info!("Restoring the storage backup");
+        let data = if legacy.exists() {
+            let data = read_to_string(legacy).context(BACKUP_RESTORE_ERROR)?;
+            serde_json::from_str(&data).context(BACKUP_RESTORE_ERROR)?
+        } else {
+            // The oldest backup that contains the file has it as it was at the backup time
+            let directories = backups[..=position]
+                .iter()
+                .rev()
+                .map(|backup| backup_directory(dirs, &backup.id))
+                .collect::<Result<Vec<_>>>()?;
+
+            let resolve = |file: &Path| {
+                let mut candidates = directories.iter().chain(once(&dirs.userdata));
+                let found = candidates.find(|directory| directory.join(file).exists());
+                found.unwrap_or(&dirs.userdata).join(file)
+            };
+
+            let raw =
+                layout::read_with(resolve, StorageScope::All).context(BACKUP_RESTORE_ERROR)?;
+            raw.context("Backup does not contain the storage")?.data
+        };
+
+        let storage = Self::parse(data).context(BACKUP_RESTORE_ERROR)?;
+        storage.write(dirs)?;
+
+        info!("Storage backup restored!");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::write;
+
+    use serde_json::json;
+    use tempfile::{TempDir, tempdir};
+    use ulid::Ulid;
+
+    use super::*;
+    use crate::components::profile::Profile;
+
+    fn directories() -> Result<(TempDir, ProjectDirs)> {
+        let directory = tempdir()?;
+        let dirs = ProjectDirs {
+            executables: directory.path().into(),
+            sysdata: directory.path().into(),
+            userdata: directory.path().into(),
+        };
+
+        Ok((directory, dirs))
+    }
+
+    #[test]
+    fn parses_backup_ids() {
+        assert_eq!(parse_backup_id("1700000000000"), Some((1700000000000, 0)));
+        assert_eq!(parse_backup_id("1700000000000-2"), Some((1700000000000, 2)));
+
+        for id in ["", "abc", "123-", "-1", "1-2-3", "+1", "../1", "1/2"] {
+            assert_eq!(parse_backup_id(id), None, "{id:?} was accepted");
+        }
+    }
+
+    #[test]
+    fn lists_backups_from_newest() -> Result<()> {
+        let (_directory, dirs) = directories()?;
+        let directory = backups_directory(&dirs);
+
+        for id in ["1000", "1000-2", "999", "1000-10", "1000-1"] {
+            create_dir_all(directory.join(id))?;
+        }
+        write(directory.join("config-998.json"), "{}")?;
+        write(directory.join("unknown.json"), "{}")?;
+
+        let backups = Storage::backups(&dirs)?;
+        let ids: Vec<&str> = backups.iter().map(|backup| backup.id.as_str()).collect();
+        assert_eq!(ids, ["1000-10", "1000-2", "1000-1", "1000", "999", "998"]);
+        Ok(())
+    }
+
+    #[test]
+    fn restores_files_from_oldest_backup_after_target() -> Result<()> {
+        let (_directory, dirs) = directories()?;
+
+        let mut storage = Storage::load(&dirs)?;
+        let profile = Profile::new(Some("First".into()), None);
+        let id = profile.ulid;
+        storage.profiles.insert(id, profile);
+        storage.write(&dirs)?;
+
+        // Each write backs up only the files it replaces
+        let mut storage = Storage::load(&dirs)?;
+        storage.profiles.get_mut(&id).unwrap().name = Some("Second".into());
+        storage.write(&dirs)?;
+
+        let mut storage = Storage::load(&dirs)?;
+        storage.profiles.get_mut(&Ulid::nil()).unwrap().description = Some("Changed".into());
+        storage.write(&dirs)?;
+
+        let mut storage = Storage::load(&dirs)?;
+        storage.profiles.get_mut(&id).unwrap().name = Some("Third".into());
+        storage.write(&dirs)?;
+
+        // The middle backup was made before the default profile changed,
+        // but it does not contain the other profile, which is taken from the newer backup
+        let backups = Storage::backups(&dirs)?;
+        assert_eq!(backups.len(), 3);
+        Storage::restore(&dirs, &backups[1].id)?;
+
+        let storage = Storage::load(&dirs)?;
+        assert_eq!(storage.profiles[&id].name.as_deref(), Some("Second"));
+        assert_eq!(storage.profiles[&Ulid::nil()].description, Profile::default().description);
+        Ok(())
+    }
+
+    #[test]
+    fn restores_legacy_backup() -> Result<()> {
+        const NIL: &str = "00000000000000000000000000";
+        let (_directory, dirs) = directories()?;
+        Storage::load(&dirs)?.write(&dirs)?;
+
+        let legacy = json!({
+            "version": 1,
+            "profiles": { NIL: { "ulid": NIL, "name": "Legacy" } },
+            "sites": {},
+        });
+        create_dir_all(backups_directory(&dirs))?;
+        write(legacy_backup_filename(&dirs, "500")?, legacy.to_string())?;
+
+        Storage::restore(&dirs, "500")?;
+
+        let storage = Storage::load(&dirs)?;
+        assert_eq!(storage.profiles[&Ulid::nil()].name.as_deref(), Some("Legacy"));
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_invalid_backup_ids() -> Result<()> {
+        let (_directory, dirs) = directories()?;
+        assert!(Storage::restore(&dirs, "../config").is_err());
+        assert!(Storage::restore(&dirs, "1000").is_err());
+        Ok(())
+    }
+}
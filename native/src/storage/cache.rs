@@ -0,0 +1,220 @@
+use std::fs::{create_dir_all, read, read_to_string};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use anyhow::{Context, Result, bail};
+use log::{debug, warn};
+use reqwest::StatusCode;
+use reqwest::blocking::{Client, Request, Response};
+use reqwest::header::{
+    CONTENT_TYPE,
+    ETAG,
+    HeaderValue,
+    IF_MODIFIED_SINCE,
+    IF_NONE_MATCH,
+    LAST_MODIFIED,
+};
+use serde::{Deserialize, Serialize};
+use tempfile::NamedTempFile;
+use url::Url;
+
+use crate::directories::ProjectDirs;
+
+const CACHE_WRITE_ERROR: &str = "Failed to write HTTP cache entry";
+
+/// The cache shared by all downloads in this process.
+static SHARED: OnceLock<Option<HttpCache>> = OnceLock::new();
+
+/// Validators and metadata of a cached response.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
+struct CacheEntry {
+    url: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    content_type: Option<String>,
+}
+
+impl CacheEntry {
+    #[inline]
+    fn validator(&self) -> Option<String> {
+        self.etag.clone().or_else(|| self.last_modified.clone())
+    }
+}
+
+/// A response that was downloaded or loaded from the cache.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct CachedResponse {
+    /// A final URL of the response, after following redirects.
+    pub url: Url,
+
+    /// A response body.
+    pub body: Vec<u8>,
+
+    /// A content type of the response, if the server provided it.
+    pub content_type: Option<String>,
+
+    /// A validator that identifies this version of the response.
+    ///
+    /// Contains the `ETag` or, if not available, the `Last-Modified` header.
+    /// Not set if the server does not provide any of them.
+    pub validator: Option<String>,
+}
+
+/// An on-disk HTTP cache for manifests and icons.
+///
+/// Responses with the `ETag` or `Last-Modified` header are stored in
+/// the user data directory, and later requests for the same URL are
+/// sent as conditional requests. When the server responds with
+/// `304 Not Modified`, the cached body is used instead.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct HttpCache {
+    directory: PathBuf,
+}
+
+impl HttpCache {
+    pub fn new(dirs: &ProjectDirs) -> Self {
+        Self { directory: dirs.userdata.join("cache/http") }
+    }
+
+    /// The cache in the user data directory, shared by all downloads in this process.
+    ///
+    /// Returns `None` if the user data directory cannot be determined.
+    pub fn shared() -> Option<&'static Self> {
+        SHARED.get_or_init(|| ProjectDirs::new().ok().map(|dirs| Self::new(&dirs))).as_ref()
+    }
+
+    /// Sends the request, conditionally if the response was cached before.
+    ///
+    /// Failures to write the cache are only logged, so the
+    /// response is still returned, but it might not be cached.
+    /// Responses with an unsuccessful status are returned as errors.
+    pub fn fetch(&self, client: &Client, mut request: Request) -> Result<CachedResponse> {
+        let key_url = request.url().to_string();
+        let key = cache_key(&key_url);
+        let (entry_path, body_path) = (self.entry_path(&key), self.body_path(&key));
+
+        let cached = read_to_string(&entry_path)
+            .ok()
+            .and_then(|data| serde_json::from_str::<CacheEntry>(&data).ok())
+            .filter(|entry| entry.url == key_url && body_path.exists());
+
+        if let Some(entry) = &cached {
+            let headers = request.headers_mut();
+            if let Some(etag) = entry.etag.as_deref().and_then(|etag| etag.parse().ok()) {
+                headers.insert(IF_NONE_MATCH, etag);
+            }
+            if let Some(date) = entry.last_modified.as_deref().and_then(|date| date.parse().ok()) {
+                headers.insert(IF_MODIFIED_SINCE, date);
+            }
+        }
+
+        let response = client.execute(request)?;
+        let url = response.url().clone();
+
+        if response.status() == StatusCode::NOT_MODIFIED
+            && let Some(entry) = cached
+        {
+            debug!("Using cached response for {url}");
+            let body = read(&body_path).context("Failed to read HTTP cache entry")?;
+            let validator = entry.validator();
+            return Ok(CachedResponse { url, body, content_type: entry.content_type, validator });
+        }
+
+        let response = check_status(response)?;
+        let header = |name| {
+            let value: Option<&HeaderValue> = response.headers().get(name);
+            value.and_then(|value| value.to_str().ok()).map(String::from)
+        };
+        let entry = CacheEntry {
+            url: key_url,
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+            content_type: header(CONTENT_TYPE),
+        };
+
+        let cacheable = entry.etag.is_some() || entry.last_modified.is_some();
+        let body = response.bytes()?.to_vec();
+
+        if cacheable && let Err(error) = self.store(&key, &entry, &body) {
+            warn!("{error:?}");
+        }
+
+        let validator = entry.validator();
+        Ok(CachedResponse { url, body, content_type: entry.content_type, validator })
+    }
+
+    fn store(&self, key: &str, entry: &CacheEntry, body: &[u8]) -> Result<()> {
+        create_dir_all(&self.directory).context(CACHE_WRITE_ERROR)?;
+
+        // The body is written first, so the entry never points to a missing or old body
+        let mut file = NamedTempFile::new_in(&self.directory).context(CACHE_WRITE_ERROR)?;
+        file.write_all(body).context(CACHE_WRITE_ERROR)?;
+        file.persist(self.body_path(key)).context(CACHE_WRITE_ERROR)?;
+
+        let mut file = NamedTempFile::new_in(&self.directory).context(CACHE_WRITE_ERROR)?;
+        serde_json::to_writer(&mut file, entry).context(CACHE_WRITE_ERROR)?;
+        file.persist(self.entry_path(key)).context(CACHE_WRITE_ERROR)?;
+
+        Ok(())
+    }
+
+    #[inline]
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.directory.join(format!("{key}.json"))
+    }
+
+    #[inline]
+    fn body_path(&self, key: &str) -> PathBuf {
+        self.directory.join(format!("{key}.body"))
+    }
+}
+
+/// Computes a stable filename-safe key of the URL.
+///
+/// Uses the 64-bit FNV-1a hash, because the standard library hasher
+/// is not guaranteed to produce the same values between releases.
+/// Collisions are detected by comparing the URL stored in the entry.
+fn cache_key(url: &str) -> String {
+    let hash = url
+        .bytes()
+        .fold(0xcbf29ce484222325u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3));
+
+    format!("{hash:016x}")
+}
+
+/// Rejects responses with an unsuccessful status.
+///
+/// Error pages are not the requested content, so they must never
+/// be parsed as manifests, icons or web app pages by the callers.
+fn check_status(response: Response) -> Result<Response> {
+    let status = response.status();
+    if !status.is_success() {
+        bail!("Server responded with status {status} for {}", response.url());
+    }
+
+    Ok(response)
+}
+
+/// Sends the request using the shared cache.
+///
+/// Falls back to a normal request if the shared cache is not available.
+/// Responses with an unsuccessful status are returned as errors.
+pub fn cached_fetch(client: &Client, request: Request) -> Result<CachedResponse> {
+    if let Some(cache) = HttpCache::shared() {
+        return cache.fetch(client, request);
+    }
+
+    let response = check_status(client.execute(request)?)?;
+    let url = response.url().clone();
+    let header = |name| {
+        let value: Option<&HeaderValue> = response.headers().get(name);
+        value.and_then(|value| value.to_str().ok()).map(String::from)
+    };
+
+    let content_type = header(CONTENT_TYPE);
+    let validator = header(ETAG).or_else(|| header(LAST_MODIFIED));
+    let body = response.bytes()?.to_vec();
+
+    Ok(CachedResponse { url, body, content_type, validator })
+}
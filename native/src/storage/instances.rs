@@ -0,0 +1,246 @@
+use std::fs::{create_dir_all, read_dir, read_to_string, remove_file};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result, bail};
+use cfg_if::cfg_if;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use tempfile::NamedTempFile;
+use ulid::Ulid;
+
+use crate::directories::ProjectDirs;
+
+const INSTANCE_WRITE_ERROR: &str = "Failed to register running web app";
+const INSTANCE_STOP_ERROR: &str = "Failed to stop running web app";
+
+/// A runtime process that was launched for a web app.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
+pub struct Instance {
+    /// A remoting name of the runtime process.
+    ///
+    /// This is the `FFPWA-<ulid>` class of the web app that started the process,
+    /// which can be a different web app in the same profile.
+    pub class: String,
+
+    /// A web app ID.
+    pub site: Ulid,
+
+    /// A profile ID of the web app.
+    pub profile: Ulid,
+
+    /// A process ID of the runtime.
+    pub pid: u32,
+
+    /// A time when the runtime was launched, in seconds since the Unix epoch.
+    pub started: u64,
+}
+
+impl Instance {
+    /// Checks whether the runtime process is still running.
+    ///
+    /// Process IDs can be reused after the process exits, so the process is also
+    /// checked to be the same runtime. On Windows, it must have been created before
+    /// the instance was registered. On other systems, it must have been started with
+    /// the remoting name of the instance.
+    pub fn is_running(&self) -> bool {
+        cfg_if! {
+            if #[cfg(platform_windows)] {
+                use windows::Win32::Foundation::{CloseHandle, FILETIME, STILL_ACTIVE};
+                use windows::Win32::System::Threading::{GetExitCodeProcess, GetProcessTimes, OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION};
+
+                unsafe {
+                    let Ok(handle) = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, self.pid) else {
+                        return false;
+                    };
+
+                    let mut code = 0;
+                    let mut created = FILETIME::default();
+                    let (mut exited, mut kernel, mut user) = (FILETIME::default(), FILETIME::default(), FILETIME::default());
+
+                    let running = GetExitCodeProcess(handle, &mut code).is_ok() && code == STILL_ACTIVE.0 as u32;
+                    let timed = GetProcessTimes(handle, &mut created, &mut exited, &mut kernel, &mut user).is_ok();
+                    let _ = CloseHandle(handle);
+
+                    // File times count 100-nanosecond intervals since 1601
+                    let created = ((created.dwHighDateTime as u64) << 32 | created.dwLowDateTime as u64) / 10_000_000;
+                    running && timed && created.saturating_sub(11_644_473_600) <= self.started
+                }
+            } else {
+                let Ok(pid) = libc::pid_t::try_from(self.pid) else { return false };
+
+                // Signal 0 only checks whether the process exists and can be signaled
+                // Processes owned by other users cannot be signaled, so they are not instances
+                unsafe { libc::kill(pid, 0) == 0 } && has_remoting_name(self.pid, &self.class)
+            }
+        }
+    }
+
+    /// Stops the runtime process.
+    ///
+    /// On Linux, BSD and macOS, the runtime is asked to quit, so it can save the session
+    /// and profile data, unless forced. On Windows, the runtime is always terminated.
+    pub fn stop(&self, force: bool) -> Result<()> {
+        // The process ID could have been reused by an unrelated process since it was checked
+        if !self.is_running() {
+            bail!("Web app is not running");
+        }
+
+        cfg_if! {
+            if #[cfg(platform_windows)] {
+                use windows::Win32::Foundation::CloseHandle;
+                use windows::Win32::System::Threading::{OpenProcess, PROCESS_TERMINATE, TerminateProcess};
+
+                let _ = force;
+                unsafe {
+                    let handle = OpenProcess(PROCESS_TERMINATE, false, self.pid).context(INSTANCE_STOP_ERROR)?;
+                    let result = TerminateProcess(handle, 0);
+                    let _ = CloseHandle(handle);
+                    result.context(INSTANCE_STOP_ERROR)?;
+                }
+            } else {
+                let pid = libc::pid_t::try_from(self.pid).context(INSTANCE_STOP_ERROR)?;
+                let signal = if force { libc::SIGKILL } else { libc::SIGTERM };
+
+                if unsafe { libc::kill(pid, signal) } != 0 {
+                    return Err(std::io::Error::last_os_error()).context(INSTANCE_STOP_ERROR);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A registry of running web apps.
+///
+/// Each launched runtime is registered in the user data directory under
+/// its `FFPWA-<ulid>` class, so later launches can hand their URLs to the
+/// running instance instead of starting another runtime on the same profile.
+/// Entries of processes that are no longer running are removed when read.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct InstanceRegistry {
+    directory: PathBuf,
+}
+
+impl InstanceRegistry {
+    pub fn new(dirs: &ProjectDirs) -> Self {
+        Self { directory: dirs.userdata.join("instances") }
+    }
+
+    /// Returns all running web apps, from the oldest to the newest.
+    pub fn list(&self) -> Vec<Instance> {
+        let Ok(entries) = read_dir(&self.directory) else { return vec![] };
+
+        let mut instances: Vec<Instance> = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|extension| extension == "json"))
+            .filter_map(|path| self.read(path))
+            .collect();
+
+        instances.sort_by_key(|instance| (instance.started, instance.site));
+        instances
+    }
+
+    /// Returns the running instance of the web app, if any.
+    pub fn get(&self, site: Ulid) -> Option<Instance> {
+        self.read(self.path(&class(site)))
+    }
+
+    /// Returns the running instance that uses the profile.
+    ///
+    /// Prefers the instance of the web app itself, because all web apps in the same
+    /// profile share one runtime process, which accepts URLs of any of them.
+    /// Stale entries whose process is not the registered runtime are skipped.
+    pub fn find(&self, site: Ulid, profile: Ulid) -> Option<Instance> {
+        self.get(site)
+            .or_else(|| self.list().into_iter().find(|instance| instance.profile == profile))
+    }
+
+    /// Registers the launched runtime process of the web app.
+    pub fn register(&self, site: Ulid, profile: Ulid, pid: u32) -> Result<Instance> {
+        let started = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let instance = Instance { class: class(site), site, profile, pid, started };
+        self.write(site, &instance)?;
+        Ok(instance)
+    }
+
+    /// Registers the web app that was handed to the running instance.
+    ///
+    /// The web app keeps the process and the remoting name of the instance,
+    /// because it is shown in the same runtime process.
+    pub fn attach(&self, site: Ulid, instance: &Instance) -> Result<Instance> {
+        let started = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let instance = Instance { site, started, ..instance.clone() };
+        self.write(site, &instance)?;
+        Ok(instance)
+    }
+
+    /// Removes the web app from the registry.
+    pub fn remove(&self, site: Ulid) {
+        let path = self.path(&class(site));
+        if path.exists()
+            && let Err(error) = remove_file(&path)
+        {
+            warn!("Failed to unregister running web app: {error}");
+        }
+    }
+
+    fn write(&self, site: Ulid, instance: &Instance) -> Result<()> {
+        create_dir_all(&self.directory).context(INSTANCE_WRITE_ERROR)?;
+        let mut file = NamedTempFile::new_in(&self.directory).context(INSTANCE_WRITE_ERROR)?;
+        serde_json::to_writer(&mut file, instance).context(INSTANCE_WRITE_ERROR)?;
+        file.persist(self.path(&class(site))).context(INSTANCE_WRITE_ERROR)?;
+        Ok(())
+    }
+
+    /// Reads the registry entry and removes it if its process is not running.
+    fn read(&self, path: PathBuf) -> Option<Instance> {
+        let data = read_to_string(&path).ok()?;
+        let instance = serde_json::from_str::<Instance>(&data).ok().filter(Instance::is_running);
+
+        if instance.is_none() {
+            let _ = remove_file(&path);
+        }
+
+        instance
+    }
+
+    #[inline]
+    fn path(&self, class: &str) -> PathBuf {
+        self.directory.join(format!("{class}.json"))
+    }
+}
+
+/// The window class and remoting name of the web app.
+#[inline]
+fn class(site: Ulid) -> String {
+    format!("FFPWA-{site}")
+}
+
+/// Checks whether the process was started with the remoting name.
+///
+/// The runtime keeps its command line, so a process that reused its
+/// ID does not have the `--name FFPWA-<ulid>` arguments of the instance.
+#[cfg(not(platform_windows))]
+fn has_remoting_name(pid: u32, name: &str) -> bool {
+    cfg_if! {
+        if #[cfg(platform_linux)] {
+            let Ok(cmdline) = std::fs::read(format!("/proc/{pid}/cmdline")) else { return false };
+            let args: Vec<&[u8]> = cmdline.split(|byte| *byte == 0).collect();
+            args.windows(2).any(|pair| pair[0] == b"--name" && pair[1] == name.as_bytes())
+        } else {
+            let Ok(output) = std::process::Command::new("ps")
+                .args(["-ww", "-o", "args=", "-p", &pid.to_string()])
+                .output()
+            else {
+                return false;
+            };
+
+            let args = String::from_utf8_lossy(&output.stdout);
+            let args: Vec<&str> = args.split_whitespace().collect();
+            args.windows(2).any(|pair| pair[0] == "--name" && pair[1] == name)
+        }
+    }
+}
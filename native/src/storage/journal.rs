@@ -0,0 +1,341 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs::{File, OpenOptions, read_to_string};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tempfile::NamedTempFile;
+use ulid::Ulid;
+
+use crate::directories::ProjectDirs;
+use crate::storage::Storage;
+
+const JOURNAL_READ_ERROR: &str = "Failed to read storage journal";
+const JOURNAL_WRITE_ERROR: &str = "Failed to write storage journal";
+
+/// How deep nested fields are compared when listing changed fields.
+///
+/// Allows reporting fields such as `config.name`
+/// without listing every changed manifest property.
+const FIELDS_DEPTH: usize = 2;
+
+/// Size of the journal in bytes after which the oldest entries are removed.
+const JOURNAL_SIZE_LIMIT: u64 = 1024 * 1024;
+
+/// The program that changes the storage in this process.
+static SOURCE: OnceLock<JournalSource> = OnceLock::new();
+
+/// Sets the program that changes the storage in this process.
+///
+/// Should be called once at startup. Defaults to [`JournalSource::Cli`].
+pub fn set_journal_source(source: JournalSource) {
+    let _ = SOURCE.set(source);
+}
+
+/// A program that changed the storage.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum JournalSource {
+    /// The console program, used directly or by scripts.
+    Cli,
+
+    /// The native connector, used by the browser extension.
+    Connector,
+}
+
+impl fmt::Display for JournalSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Cli => write!(f, "CLI"),
+            Self::Connector => write!(f, "connector"),
+        }
+    }
+}
+
+/// A kind of the storage change.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum JournalChange {
+    Created,
+    Updated,
+    Removed,
+
+    /// The whole storage was replaced, for example, when restoring a backup.
+    Replaced,
+}
+
+/// An object that was changed.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
+#[serde(tag = "type", content = "id", rename_all = "lowercase")]
+pub enum JournalTarget {
+    Site(Ulid),
+    Profile(Ulid),
+
+    /// Config, runtime arguments and variables, and other storage settings.
+    Settings,
+
+    /// The whole storage.
+    Storage,
+}
+
+/// A single recorded storage change.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
+pub struct JournalEntry {
+    /// A time when the change was made, in milliseconds since the Unix epoch.
+    pub timestamp: u64,
+
+    /// A program that made the change.
+    pub source: JournalSource,
+
+    /// A kind of the change.
+    pub change: JournalChange,
+
+    /// An object that was changed.
+    pub target: JournalTarget,
+
+    /// A name of the changed web app or profile, if it has one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    /// Names of the changed fields.
+    ///
+    /// Only set for updates. Nested fields are separated with a dot.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<String>,
+}
+
+impl JournalEntry {
+    /// Time when the change was made.
+    #[inline]
+    pub fn created(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(self.timestamp)
+    }
+}
+
+impl fmt::Display for JournalEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let change = match self.change {
+            JournalChange::Created => "Created",
+            JournalChange::Updated => "Updated",
+            JournalChange::Removed => "Removed",
+            JournalChange::Replaced => "Replaced",
+        };
+
+        match &self.target {
+            JournalTarget::Site(id) => write!(f, "{change} web app {id}")?,
+            JournalTarget::Profile(id) => write!(f, "{change} profile {id}")?,
+            JournalTarget::Settings => write!(f, "{change} settings")?,
+            JournalTarget::Storage => write!(f, "{change} storage")?,
+        }
+
+        if let Some(name) = &self.name {
+            write!(f, " ({name})")?;
+        }
+
+        if !self.fields.is_empty() {
+            write!(f, ": {}", self.fields.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[inline]
+fn journal_filename(dirs: &ProjectDirs) -> PathBuf {
+    dirs.userdata.join("journal.jsonl")
+}
+
+/// Collects names of fields that differ between two values.
+fn changed_fields(old: &Value, new: &Value, prefix: &str, depth: usize, fields: &mut Vec<String>) {
+    match (old, new) {
+        (Value::Object(old), Value::Object(new)) if depth > 0 => {
+            let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+
+            for key in keys {
+                let name = if prefix.is_empty() { key.clone() } else { format!("{prefix}.{key}") };
+                let (old, new) = (old.get(key), new.get(key));
+
+                if old != new {
+                    let (old, new) = (old.unwrap_or(&Value::Null), new.unwrap_or(&Value::Null));
+                    changed_fields(old, new, &name, depth - 1, fields);
+                }
+            }
+        }
+        _ if old != new => fields.push(prefix.into()),
+        _ => {}
+    }
+}
+
+impl Storage {
+    /// Lists changes of this storage since it was loaded.
+    ///
+    /// Storage without a snapshot replaces the whole storage,
+    /// so it is recorded as a single change.
+    pub(super) fn changes(&self, index: &Map<String, Value>) -> Result<Vec<JournalEntry>> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
+        let source = *SOURCE.get_or_init(|| JournalSource::Cli);
+
+        let entry = |change, target, name, fields| JournalEntry {
+            timestamp,
+            source,
+            change,
+            target,
+            name,
+            fields,
+        };
+
+        let Some(snapshot) = &self.snapshot else {
+            return Ok(vec![entry(JournalChange::Replaced, JournalTarget::Storage, None, vec![])]);
+        };
+
+        let mut entries = vec![];
+
+        let mut fields = vec![];
+        let (old, new) = (Value::Object(snapshot.index.clone()), Value::Object(index.clone()));
+        changed_fields(&old, &new, "", FIELDS_DEPTH, &mut fields);
+        fields.retain(|field| field != "version");
+
+        if !fields.is_empty() {
+            entries.push(entry(JournalChange::Updated, JournalTarget::Settings, None, fields));
+        }
+
+        for (id, profile) in &snapshot.profiles {
+            if !self.profiles.contains_key(id) {
+                let target = JournalTarget::Profile(*id);
+                entries.push(entry(JournalChange::Removed, target, profile.name.clone(), vec![]));
+            }
+        }
+
+        for (id, profile) in &self.profiles {
+            let target = JournalTarget::Profile(*id);
+            let name = profile.name.clone();
+
+            match snapshot.profiles.get(id) {
+                None => entries.push(entry(JournalChange::Created, target, name, vec![])),
+                Some(old) if old != profile => {
+                    let mut fields = vec![];
+                    let (old, new) = (serde_json::to_value(old)?, serde_json::to_value(profile)?);
+                    changed_fields(&old, &new, "", FIELDS_DEPTH, &mut fields);
+                    entries.push(entry(JournalChange::Updated, target, name, fields));
+                }
+                _ => {}
+            }
+        }
+
+        for (id, site) in &snapshot.sites {
+            if !self.sites.contains_key(id) {
+                let target = JournalTarget::Site(*id);
+                entries.push(entry(JournalChange::Removed, target, Some(site.name()), vec![]));
+            }
+        }
+
+        for (id, site) in &self.sites {
+            let target = JournalTarget::Site(*id);
+            let name = Some(site.name());
+
+            match snapshot.sites.get(id) {
+                None => entries.push(entry(JournalChange::Created, target, name, vec![])),
+                Some(old) if old != site => {
+                    let mut fields = vec![];
+                    let (old, new) = (serde_json::to_value(old)?, serde_json::to_value(site)?);
+                    changed_fields(&old, &new, "", FIELDS_DEPTH, &mut fields);
+                    entries.push(entry(JournalChange::Updated, target, name, fields));
+                }
+                _ => {}
+            }
+        }
+
+        Ok(entries)
+    }
+
+    /// Appends changes to the journal in the user data directory.
+    ///
+    /// Once the journal grows over its size limit, the oldest
+    /// entries are removed, so it only keeps the recent history.
+    pub(super) fn record(dirs: &ProjectDirs, entries: &[JournalEntry]) -> Result<()> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+
+        let filename = journal_filename(dirs);
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&filename)
+            .context(JOURNAL_WRITE_ERROR)?;
+        let mut writer = BufWriter::new(file);
+
+        for entry in entries {
+            serde_json::to_writer(&mut writer, entry).context(JOURNAL_WRITE_ERROR)?;
+            writeln!(writer).context(JOURNAL_WRITE_ERROR)?;
+        }
+
+        writer.flush().context(JOURNAL_WRITE_ERROR)?;
+
+        let size = writer.get_ref().metadata().context(JOURNAL_WRITE_ERROR)?.len();
+        if size > JOURNAL_SIZE_LIMIT {
+            Self::trim(dirs).context(JOURNAL_WRITE_ERROR)?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes the oldest entries, keeping the newest ones within half of the size limit.
+    fn trim(dirs: &ProjectDirs) -> Result<()> {
+        let filename = journal_filename(dirs);
+        let data = read_to_string(&filename)?;
+
+        let mut size = 0;
+        let mut lines: Vec<&str> = data
+            .lines()
+            .rev()
+            .take_while(|line| {
+                size += line.len() as u64 + 1;
+                size <= JOURNAL_SIZE_LIMIT / 2
+            })
+            .collect();
+        lines.reverse();
+
+        let file = NamedTempFile::new_in(&dirs.userdata)?;
+        let mut writer = BufWriter::new(file);
+
+        for line in lines {
+            writeln!(writer, "{line}")?;
+        }
+
+        writer.into_inner()?.persist(filename)?;
+        Ok(())
+    }
+
+    /// Reads all recorded storage changes, from oldest to newest.
+    pub fn history(dirs: &ProjectDirs) -> Result<Vec<JournalEntry>> {
+        let filename = journal_filename(dirs);
+        let mut entries = vec![];
+
+        if !filename.exists() {
+            return Ok(entries);
+        }
+
+        let file = File::open(filename).context(JOURNAL_READ_ERROR)?;
+        for line in BufReader::new(file).lines() {
+            let line = line.context(JOURNAL_READ_ERROR)?;
+
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            match serde_json::from_str(&line) {
+                Ok(entry) => entries.push(entry),
+                Err(error) => warn!("Ignoring invalid journal entry: {error}"),
+            }
+        }
+
+        Ok(entries)
+    }
+}
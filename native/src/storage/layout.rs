@@ -0,0 +1,477 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{create_dir_all, read_dir, read_to_string, remove_file};
+use std::io::{BufWriter, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use log::warn;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use tempfile::NamedTempFile;
+use ulid::Ulid;
+
+use crate::components::profile::Profile;
+use crate::components::site::Site;
+use crate::directories::ProjectDirs;
+use crate::storage::Storage;
+
+/// The first storage version that is split into multiple files.
+///
+/// Older versions keep all profiles and web apps in a single file.
+pub const SPLIT_STORAGE_VERSION: u32 = 2;
+
+/// Parts of the storage that should be loaded.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum StorageScope {
+    /// All profiles and web apps.
+    All,
+
+    /// Only the index, without any profiles and web apps.
+    Index,
+
+    /// All web apps, without any profiles.
+    Sites,
+
+    /// A single web app and its profile.
+    Site(Ulid),
+}
+
+/// IDs of profiles and web apps listed in the index.
+#[derive(Debug, Eq, PartialEq, Clone, Default)]
+pub struct StorageIds {
+    pub profiles: BTreeSet<Ulid>,
+    pub sites: BTreeSet<Ulid>,
+}
+
+/// Storage data as it was read from the disk.
+pub struct RawStorage {
+    /// Storage data in the single-file shape, with profiles and web apps
+    /// included in the data, so it can be migrated and deserialized.
+    pub data: Value,
+
+    /// IDs listed in the index.
+    ///
+    /// Not set if the storage was written in the single-file layout.
+    pub ids: Option<StorageIds>,
+}
+
+/// Profiles and web apps as they were loaded, used to only write changed files.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Snapshot {
+    pub ids: StorageIds,
+    pub index: Map<String, Value>,
+    pub profiles: BTreeMap<Ulid, Profile>,
+    pub sites: BTreeMap<Ulid, Site>,
+}
+
+// Paths of the storage files, relative to the user data directory
+const INDEX_FILENAME: &str = "config.json";
+const PROFILES_DIRECTORY: &str = "storage/profiles";
+const SITES_DIRECTORY: &str = "storage/sites";
+
+#[inline]
+fn entry_filename(directory: &str, id: Ulid) -> PathBuf {
+    Path::new(directory).join(format!("{id}.json"))
+}
+
+fn read_json(filename: &Path) -> Result<Value> {
+    let data = read_to_string(filename)?;
+    Ok(serde_json::from_str(&data)?)
+}
+
+fn write_json<T: Serialize>(directory: &Path, filename: &Path, value: &T) -> Result<()> {
+    create_dir_all(directory)?;
+
+    let file = NamedTempFile::new_in(directory)?;
+    let mut writer = BufWriter::new(file);
+
+    if cfg!(debug_assertions) {
+        serde_json::to_writer_pretty(&mut writer, value)?;
+    } else {
+        serde_json::to_writer(&mut writer, value)?;
+    }
+
+    let file = writer.into_inner()?;
+    file.as_file().sync_all()?;
+    file.persist(filename)?;
+
+    Ok(())
+}
+
+fn remove_json(filename: &Path) -> Result<()> {
+    match remove_file(filename) {
+        Err(error) if error.kind() != ErrorKind::NotFound => Err(error.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Reads entries with the provided IDs into a map.
+///
+/// Missing entries are skipped with a warning, so a single
+/// missing file does not prevent loading the rest of the storage.
+fn read_entries(
+    resolve: &impl Fn(&Path) -> PathBuf,
+    directory: &str,
+    ids: impl Iterator<Item = Ulid>,
+) -> Result<Map<String, Value>> {
+    let mut entries = Map::new();
+
+    for id in ids {
+        let filename = resolve(&entry_filename(directory, id));
+
+        if !filename.exists() {
+            warn!("Storage entry {id} is missing, run `firefoxpwa doctor` to check the storage");
+            continue;
+        }
+
+        let entry = read_json(&filename).with_context(|| format!("Failed to read {id}"))?;
+        entries.insert(id.to_string(), entry);
+    }
+
+    Ok(entries)
+}
+
+/// Lists IDs of all entries stored in the directory.
+fn list_entries(directory: &Path) -> Result<BTreeSet<Ulid>> {
+    let mut ids = BTreeSet::new();
+
+    if !directory.exists() {
+        return Ok(ids);
+    }
+
+    for entry in read_dir(directory)? {
+        let filename = entry?.file_name();
+        let id = filename.to_str().and_then(|name| name.strip_suffix(".json"));
+
+        if let Some(id) = id.and_then(|id| Ulid::from_string(id).ok()) {
+            ids.insert(id);
+        }
+    }
+
+    Ok(ids)
+}
+
+/// Reads the storage from the disk.
+///
+/// Returns `None` if the storage has not been written yet. Storage in the
+/// single-file layout is always read completely, regardless of the scope.
+#[inline]
+pub fn read(dirs: &ProjectDirs, scope: StorageScope) -> Result<Option<RawStorage>> {
+    read_with(|file| dirs.userdata.join(file), scope)
+}
+
+/// Reads the storage from files found with the provided function.
+///
+/// The function receives paths relative to the user data directory,
+/// and returns paths of the files that should be read instead.
+pub fn read_with(
+    resolve: impl Fn(&Path) -> PathBuf,
+    scope: StorageScope,
+) -> Result<Option<RawStorage>> {
+    let filename = resolve(Path::new(INDEX_FILENAME));
+
+    if !filename.exists() {
+        return Ok(None);
+    }
+
+    let mut data = read_json(&filename)?;
+    let index = data.as_object_mut().context("Storage is not an object")?;
+
+    let version = index.get("version").and_then(Value::as_u64).unwrap_or(0);
+    if version < SPLIT_STORAGE_VERSION as u64 {
+        return Ok(Some(RawStorage { data, ids: None }));
+    }
+
+    let mut ids = StorageIds::default();
+    if let Some(profiles) = index.remove("profiles") {
+        ids.profiles = serde_json::from_value(profiles)?;
+    }
+    if let Some(sites) = index.remove("sites") {
+        ids.sites = serde_json::from_value(sites)?;
+    }
+
+    let sites = match scope {
+        StorageScope::All | StorageScope::Sites => {
+            read_entries(&resolve, SITES_DIRECTORY, ids.sites.iter().copied())?
+        }
+        StorageScope::Index => Map::new(),
+        StorageScope::Site(id) => {
+            let id = ids.sites.get(&id).copied();
+            read_entries(&resolve, SITES_DIRECTORY, id.into_iter())?
+        }
+    };
+
+    let profiles = match scope {
+        StorageScope::All => ids.profiles.iter().copied().collect(),
+        StorageScope::Index | StorageScope::Sites => vec![],
+        StorageScope::Site(_) => sites
+            .values()
+            .filter_map(|site| site.get("profile")?.as_str()?.parse().ok())
+            .filter(|id| ids.profiles.contains(id))
+            .collect(),
+    };
+    let profiles = read_entries(&resolve, PROFILES_DIRECTORY, profiles.into_iter())?;
+
+    index.insert("profiles".into(), Value::Object(profiles));
+    index.insert("sites".into(), Value::Object(sites));
+
+    Ok(Some(RawStorage { data, ids: Some(ids) }))
+}
+
+/// Files that need to be written and removed to save the storage.
+pub struct WritePlan {
+    /// IDs that will be listed in the index.
+    ids: StorageIds,
+
+    /// IDs of entries that will be removed.
+    removed: StorageIds,
+
+    /// IDs of entries that will be written.
+    written: StorageIds,
+}
+
+impl WritePlan {
+    /// Paths of all files that will be replaced or removed, relative to the user data directory.
+    ///
+    /// Files that do not exist yet are also included.
+    pub fn replaced_files(&self) -> Vec<PathBuf> {
+        let profiles = self.written.profiles.iter().chain(&self.removed.profiles);
+        let sites = self.written.sites.iter().chain(&self.removed.sites);
+
+        let mut files = vec![PathBuf::from(INDEX_FILENAME)];
+        files.extend(profiles.map(|id| entry_filename(PROFILES_DIRECTORY, *id)));
+        files.extend(sites.map(|id| entry_filename(SITES_DIRECTORY, *id)));
+        files
+    }
+}
+
+/// Determines which files need to be written and removed to save the storage.
+///
+/// Only profiles and web apps that changed since they were loaded are
+/// written, and entries that were not loaded are left untouched. Storage
+/// without a snapshot replaces all existing entries.
+pub fn plan(dirs: &ProjectDirs, storage: &Storage) -> Result<WritePlan> {
+    let (ids, removed) = match &storage.snapshot {
+        Some(snapshot) => {
+            let removed = StorageIds {
+                profiles: snapshot
+                    .profiles
+                    .keys()
+                    .filter(|id| !storage.profiles.contains_key(id))
+                    .copied()
+                    .collect(),
+                sites: snapshot
+                    .sites
+                    .keys()
+                    .filter(|id| !storage.sites.contains_key(id))
+                    .copied()
+                    .collect(),
+            };
+
+            let ids = StorageIds {
+                profiles: (&snapshot.ids.profiles - &removed.profiles)
+                    .into_iter()
+                    .chain(storage.profiles.keys().copied())
+                    .collect(),
+                sites: (&snapshot.ids.sites - &removed.sites)
+                    .into_iter()
+                    .chain(storage.sites.keys().copied())
+                    .collect(),
+            };
+
+            (ids, removed)
+        }
+        None => {
+            let ids = StorageIds {
+                profiles: storage.profiles.keys().copied().collect(),
+                sites: storage.sites.keys().copied().collect(),
+            };
+
+            let removed = StorageIds {
+                profiles: &list_entries(&dirs.userdata.join(PROFILES_DIRECTORY))? - &ids.profiles,
+                sites: &list_entries(&dirs.userdata.join(SITES_DIRECTORY))? - &ids.sites,
+            };
+
+            (ids, removed)
+        }
+    };
+
+    let mut written = StorageIds::default();
+    let snapshot = storage.snapshot.as_ref();
+
+    for (id, profile) in &storage.profiles {
+        let unchanged = snapshot.and_then(|snapshot| snapshot.profiles.get(id));
+        if unchanged != Some(profile) {
+            written.profiles.insert(*id);
+        }
+    }
+
+    for (id, site) in &storage.sites {
+        let unchanged = snapshot.and_then(|snapshot| snapshot.sites.get(id));
+        if unchanged != Some(site) {
+            written.sites.insert(*id);
+        }
+    }
+
+    Ok(WritePlan { ids, removed, written })
+}
+
+/// Writes the storage to the disk according to the plan.
+pub fn write(
+    dirs: &ProjectDirs,
+    storage: &Storage,
+    plan: WritePlan,
+    mut index: Map<String, Value>,
+) -> Result<()> {
+    let profiles_directory = dirs.userdata.join(PROFILES_DIRECTORY);
+    let sites_directory = dirs.userdata.join(SITES_DIRECTORY);
+
+    for id in &plan.written.profiles {
+        let filename = dirs.userdata.join(entry_filename(PROFILES_DIRECTORY, *id));
+        write_json(&profiles_directory, &filename, &storage.profiles[id])?;
+    }
+
+    for id in &plan.written.sites {
+        let filename = dirs.userdata.join(entry_filename(SITES_DIRECTORY, *id));
+        write_json(&sites_directory, &filename, &storage.sites[id])?;
+    }
+
+    // The index is written after the entries, so it never lists missing entries
+    index.insert("profiles".into(), serde_json::to_value(&plan.ids.profiles)?);
+    index.insert("sites".into(), serde_json::to_value(&plan.ids.sites)?);
+    write_json(&dirs.userdata, &dirs.userdata.join(INDEX_FILENAME), &index)?;
+
+    for id in plan.removed.profiles {
+        remove_json(&dirs.userdata.join(entry_filename(PROFILES_DIRECTORY, id)))?;
+    }
+
+    for id in plan.removed.sites {
+        remove_json(&dirs.userdata.join(entry_filename(SITES_DIRECTORY, id)))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use tempfile::{TempDir, tempdir};
+    use url::Url;
+
+    use super::*;
+    use crate::components::site::SiteManifest;
+
+    fn directories() -> Result<(TempDir, ProjectDirs)> {
+        let directory = tempdir()?;
+        let dirs = ProjectDirs {
+            executables: directory.path().into(),
+            sysdata: directory.path().into(),
+            userdata: directory.path().into(),
+        };
+
+        Ok((directory, dirs))
+    }
+
+    fn site() -> Result<Site> {
+        let url = Url::parse("https://example.com/")?;
+        let mut manifest: SiteManifest = serde_json::from_str(r#"{ "start_url": "/" }"#)?;
+        manifest.process(&url, &url)?;
+
+        Ok(Site {
+            ulid: Ulid::new(),
+            profile: Ulid::nil(),
+            config: serde_json::from_value(json!({ "document_url": url, "manifest_url": url }))?,
+            manifest,
+            extras: None,
+            validators: BTreeMap::new(),
+            last_updated: None,
+        })
+    }
+
+    /// Writes new storage with the web apps and returns their IDs.
+    fn populate(dirs: &ProjectDirs, count: usize) -> Result<Vec<Ulid>> {
+        let mut storage = Storage::load(dirs)?;
+        let mut ids = vec![];
+
+        for _ in 0..count {
+            let site = site()?;
+            ids.push(site.ulid);
+            storage.sites.insert(site.ulid, site);
+        }
+
+        storage.write(dirs)?;
+        Ok(ids)
+    }
+
+    #[test]
+    fn plan_without_snapshot_replaces_all_entries() -> Result<()> {
+        let (_directory, dirs) = directories()?;
+
+        let stale = Ulid::new();
+        let filename = dirs.userdata.join(entry_filename(PROFILES_DIRECTORY, stale));
+        write_json(&dirs.userdata.join(PROFILES_DIRECTORY), &filename, &Profile::default())?;
+
+        let storage = Storage::default();
+        let plan = plan(&dirs, &storage)?;
+
+        assert_eq!(plan.ids.profiles, BTreeSet::from([Ulid::nil()]));
+        assert_eq!(plan.written.profiles, BTreeSet::from([Ulid::nil()]));
+        assert_eq!(plan.removed.profiles, BTreeSet::from([stale]));
+        Ok(())
+    }
+
+    #[test]
+    fn plan_removes_deleted_site() -> Result<()> {
+        let (_directory, dirs) = directories()?;
+        let ids = populate(&dirs, 2)?;
+
+        let mut storage = Storage::load(&dirs)?;
+        storage.sites.remove(&ids[0]);
+
+        let plan = plan(&dirs, &storage)?;
+        assert_eq!(plan.ids.sites, BTreeSet::from([ids[1]]));
+        assert_eq!(plan.removed.sites, BTreeSet::from([ids[0]]));
+        assert!(plan.written.sites.is_empty());
+
+        storage.write(&dirs)?;
+        assert!(!dirs.userdata.join(entry_filename(SITES_DIRECTORY, ids[0])).exists());
+        assert!(dirs.userdata.join(entry_filename(SITES_DIRECTORY, ids[1])).exists());
+        Ok(())
+    }
+
+    #[test]
+    fn plan_keeps_entries_that_were_not_loaded() -> Result<()> {
+        let (_directory, dirs) = directories()?;
+        let ids = populate(&dirs, 2)?;
+
+        let mut storage = Storage::load_site(&dirs, ids[0])?;
+        storage.sites.get_mut(&ids[0]).unwrap().config.name = Some("Changed".into());
+
+        let plan = plan(&dirs, &storage)?;
+        assert_eq!(plan.ids.sites, ids.iter().copied().collect::<BTreeSet<_>>());
+        assert!(plan.removed.sites.is_empty());
+        assert!(plan.removed.profiles.is_empty());
+
+        storage.write(&dirs)?;
+        let storage = Storage::load(&dirs)?;
+        assert_eq!(storage.sites.len(), 2);
+        assert!(storage.profiles.contains_key(&Ulid::nil()));
+        assert_eq!(storage.sites[&ids[0]].config.name.as_deref(), Some("Changed"));
+        Ok(())
+    }
+
+    #[test]
+    fn plan_does_not_rewrite_unchanged_entries() -> Result<()> {
+        let (_directory, dirs) = directories()?;
+        let ids = populate(&dirs, 2)?;
+
+        let mut storage = Storage::load(&dirs)?;
+        storage.sites.get_mut(&ids[1]).unwrap().config.name = Some("Changed".into());
+
+        let plan = plan(&dirs, &storage)?;
+        assert_eq!(plan.written.sites, BTreeSet::from([ids[1]]));
+        assert!(plan.written.profiles.is_empty());
+        assert!(!plan.replaced_files().contains(&entry_filename(SITES_DIRECTORY, ids[0])));
+        Ok(())
+    }
+}
@@ -0,0 +1,103 @@
+use anyhow::{Context, Result, bail};
+use serde_json::{Map, Value};
+
+/// Current version of the storage schema.
+///
+/// Needs to be increased whenever the storage layout changes in a way
+/// that cannot be handled by default values. Each increase also requires
+/// adding a migration from the previous version to [`MIGRATIONS`].
+pub const STORAGE_VERSION: u32 = 2;
+
+/// A single migration step that upgrades storage by one version.
+type Migration = fn(&mut Map<String, Value>) -> Result<()>;
+
+/// All migration steps, in order.
+///
+/// The migration at index `n` upgrades storage from version `n`
+/// to version `n + 1`, so the list length must always match the
+/// current [`STORAGE_VERSION`].
+const MIGRATIONS: [Migration; STORAGE_VERSION as usize] = [migrate_0_to_1, migrate_1_to_2];
+
+/// Version 0 is the layout before the storage became versioned.
+///
+/// Apart from the missing version field, it is the same as version 1,
+/// so there is nothing to change here.
+fn migrate_0_to_1(_storage: &mut Map<String, Value>) -> Result<()> {
+    Ok(())
+}
+
+/// Version 2 splits the storage into an index and a file for each profile
+/// and web app. Migrations always work with the single-file shape, so the
+/// layout change is handled when reading and writing the storage instead.
+fn migrate_1_to_2(_storage: &mut Map<String, Value>) -> Result<()> {
+    Ok(())
+}
+
+/// Upgrades storage data to the current schema version.
+///
+/// Storage without a version field is treated as version 0. Storage
+/// written by a newer version of the program is rejected, as loading it
+/// could silently drop fields that this version does not know about.
+pub fn migrate(data: &mut Value) -> Result<()> {
+    let storage = data.as_object_mut().context("Storage is not an object")?;
+
+    let version = match storage.get("version") {
+        Some(version) => version.as_u64().context("Storage version is not a number")?,
+        None => 0,
+    };
+
+    if version > STORAGE_VERSION as u64 {
+        bail!(
+            "Storage was written by a newer version of PWAsForFirefox (schema version {version}, supported {STORAGE_VERSION}), please update this program"
+        );
+    }
+
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        migration(storage)
+            .with_context(|| format!("Failed to migrate storage from version {from}"))?;
+    }
+
+    storage.insert("version".into(), STORAGE_VERSION.into());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn every_version_has_a_migration() {
+        assert_eq!(MIGRATIONS.len(), STORAGE_VERSION as usize);
+    }
+
+    #[test]
+    fn unversioned_storage_is_stamped_with_current_version() -> Result<()> {
+        let mut data = json!({ "profiles": {}, "sites": {}, "arguments": ["--example"] });
+        migrate(&mut data)?;
+
+        assert_eq!(data["version"], json!(STORAGE_VERSION));
+        assert_eq!(data["arguments"], json!(["--example"]));
+        Ok(())
+    }
+
+    #[test]
+    fn current_storage_is_unchanged() -> Result<()> {
+        let mut data = json!({ "version": STORAGE_VERSION, "profiles": {}, "sites": {} });
+        let original = data.clone();
+        migrate(&mut data)?;
+
+        assert_eq!(data, original);
+        Ok(())
+    }
+
+    #[test]
+    fn newer_and_invalid_versions_are_rejected() {
+        let mut data = json!({ "version": STORAGE_VERSION + 1 });
+        assert!(migrate(&mut data).is_err());
+
+        let mut data = json!({ "version": "1" });
+        assert!(migrate(&mut data).is_err());
+    }
+}
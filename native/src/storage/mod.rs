@@ -0,0 +1,364 @@
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::sync::{Arc, Mutex, Weak};
+
+use anyhow::{Context, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use smart_default::SmartDefault;
+use ulid::Ulid;
+
+use crate::components::profile::Profile;
+use crate::components::site::Site;
+use crate::directories::ProjectDirs;
+use crate::storage::layout::{Snapshot, StorageIds, StorageScope};
+use crate::storage::migrations::{STORAGE_VERSION, migrate};
+
+mod backups;
+mod cache;
+mod instances;
+mod journal;
+mod layout;
+mod migrations;
+mod policies;
+
+pub use backups::StorageBackup;
+pub use cache::{CachedResponse, HttpCache, cached_fetch};
+pub use instances::{Instance, InstanceRegistry};
+pub use journal::{JournalChange, JournalEntry, JournalSource, JournalTarget, set_journal_source};
+pub use policies::Policies;
+
+const STORAGE_LOAD_ERROR: &str = "Failed to load storage";
+const STORAGE_SAVE_ERROR: &str = "Failed to save storage";
+const STORAGE_LOCK_ERROR: &str = "Failed to lock storage";
+
+/// The storage lock that is currently held by this process.
+///
+/// Used to make [`StorageLock`] re-entrant, so nested commands that
+/// lock the storage in the same process do not deadlock themselves.
+static HELD_LOCK: Mutex<Weak<File>> = Mutex::new(Weak::new());
+
+/// An exclusive advisory lock on the storage.
+///
+/// The lock is held on a separate `config.lock` file in the user data
+/// directory, so it is not affected by the storage file being replaced.
+/// It is released once all guards obtained in this process are dropped.
+#[derive(Debug, Clone)]
+pub struct StorageLock(#[allow(dead_code)] Arc<File>);
+
+impl StorageLock {
+    pub fn acquire(dirs: &ProjectDirs) -> Result<Self> {
+        let mut held = HELD_LOCK.lock().unwrap_or_else(|error| error.into_inner());
+
+        if let Some(file) = held.upgrade() {
+            return Ok(Self(file));
+        }
+
+        let filename = dirs.userdata.join("config.lock");
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(filename)
+            .context(STORAGE_LOCK_ERROR)?;
+        file.lock().context(STORAGE_LOCK_ERROR)?;
+
+        let file = Arc::new(file);
+        *held = Arc::downgrade(&file);
+        Ok(Self(file))
+    }
+}
+
+#[non_exhaustive]
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, SmartDefault)]
+#[serde(default)]
+pub struct Config {
+    /// Whether the runtime and profile should always be patched when launching a web app.
+    ///
+    /// Does not have any effect on macOS, where web apps are
+    /// always patched to correctly display their names.
+    pub always_patch: bool,
+
+    /// Whether the runtime should use Wayland Display Server.
+    ///
+    /// This sets `MOZ_ENABLE_WAYLAND` environment variable.
+    ///
+    /// Only affects Linux, on supported desktop environments.
+    /// May be overwritten with a system environment variable.
+    pub runtime_enable_wayland: bool,
+
+    /// Whether the runtime should use X Input Extension 2.
+    ///
+    /// This sets `MOZ_USE_XINPUT2` environment variable.
+    ///
+    /// Only affects Linux, on supported desktop environments.
+    /// May be overwritten with a system environment variable.
+    pub runtime_use_xinput2: bool,
+
+    /// Whether the runtime should use XDG Desktop Portals.
+    ///
+    /// This sets `GTK_USE_PORTAL` environment variable.
+    ///
+    /// Only affects Linux, on supported desktop environments.
+    /// May be overwritten with a system environment variable.
+    pub runtime_use_portals: bool,
+
+    #[cfg(platform_linux)]
+    /// Experimental: Using the system runtime to save some disk space.
+    /// This might not work on your system.
+    pub use_linked_runtime: bool,
+}
+
+/// Stable keys of profiles and web apps managed by a desired-state file.
+///
+/// Maps user-chosen keys from the file to the generated IDs,
+/// so re-applying the same file updates existing profiles and
+/// web apps instead of creating new ones.
+#[non_exhaustive]
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Default)]
+#[serde(default)]
+pub struct ManagedKeys {
+    /// A map of profile keys and their IDs.
+    pub profiles: BTreeMap<String, Ulid>,
+
+    /// A map of web app keys and their IDs.
+    pub sites: BTreeMap<String, Ulid>,
+}
+
+#[non_exhaustive]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, SmartDefault)]
+#[serde(default)]
+pub struct Storage {
+    /// A version of the storage schema.
+    ///
+    /// Used to migrate storage written by older versions of the program
+    /// and to refuse loading storage written by newer versions.
+    #[default(STORAGE_VERSION)]
+    pub version: u32,
+
+    /// A map of profiles and their IDs.
+    #[default([(Ulid::nil(), Profile::default())].iter().cloned().collect())]
+    pub profiles: BTreeMap<Ulid, Profile>,
+
+    /// A map of web apps and their IDs.
+    pub sites: BTreeMap<Ulid, Site>,
+
+    /// Arguments to be passed to the Firefox runtime.
+    pub arguments: Vec<String>,
+
+    /// Environment variables to be passed to the Firefox runtime.
+    pub variables: BTreeMap<String, String>,
+
+    /// Config of the native program.
+    pub config: Config,
+
+    /// Profiles and web apps managed by a desired-state file.
+    pub managed: ManagedKeys,
+
+    /// Default values from system-wide policies that were already applied.
+    ///
+    /// Used to only apply each default value once, so users can change it.
+    policy_defaults: Map<String, Value>,
+
+    /// System-wide policies that were applied when loading the storage.
+    #[serde(skip)]
+    pub policies: Policies,
+
+    /// Profiles and web apps as they were loaded.
+    #[serde(skip)]
+    snapshot: Option<Snapshot>,
+}
+
+impl Storage {
+    /// Locks the storage for modifications.
+    ///
+    /// The returned guard must be held across the whole load-modify-write
+    /// cycle to prevent concurrent processes from overwriting each other's
+    /// changes. Reading the storage does not require a lock.
+    #[inline]
+    pub fn lock(dirs: &ProjectDirs) -> Result<StorageLock> {
+        StorageLock::acquire(dirs)
+    }
+
+    /// Loads the whole storage from the user data directory.
+    ///
+    /// System-wide policies are applied to the loaded storage,
+    /// so locked keys always have their enforced values.
+    #[inline]
+    pub fn load(dirs: &ProjectDirs) -> Result<Self> {
+        Self::load_scope(dirs, StorageScope::All)
+    }
+
+    /// Loads only the storage index, without any profiles and web apps.
+    ///
+    /// Useful for commands that only need the config or runtime settings.
+    #[inline]
+    pub fn load_index(dirs: &ProjectDirs) -> Result<Self> {
+        Self::load_scope(dirs, StorageScope::Index)
+    }
+
+    /// Loads the storage index and all web apps, without any profiles.
+    ///
+    /// Useful for commands that need to find web apps, but do not use profiles.
+    #[inline]
+    pub fn load_sites(dirs: &ProjectDirs) -> Result<Self> {
+        Self::load_scope(dirs, StorageScope::Sites)
+    }
+
+    /// Loads the storage index, a single web app and its profile.
+    ///
+    /// Useful for commands that only work with one web app. If the
+    /// web app does not exist, it is simply missing from the storage.
+    #[inline]
+    pub fn load_site(dirs: &ProjectDirs, id: Ulid) -> Result<Self> {
+        Self::load_scope(dirs, StorageScope::Site(id))
+    }
+
+    fn load_scope(dirs: &ProjectDirs, scope: StorageScope) -> Result<Self> {
+        let policies = Policies::load(dirs)?;
+
+        let raw = layout::read(dirs, scope).context(STORAGE_LOAD_ERROR)?;
+        let exists = raw.is_some();
+
+        let (mut data, ids) = match raw {
+            Some(raw) => (raw.data, raw.ids),
+            None => (Value::Object(Map::new()), Some(StorageIds::default())),
+        };
+
+        migrate(&mut data).context(STORAGE_LOAD_ERROR)?;
+        policies.apply(&mut data).context(STORAGE_LOAD_ERROR)?;
+
+        let mut storage: Self = serde_json::from_value(data).context(STORAGE_LOAD_ERROR)?;
+        storage.policies = policies;
+
+        // The index is taken from the parsed storage, so fields missing from older or
+        // new storage get their default values and are not recorded as changed
+        let index = storage.index().context(STORAGE_LOAD_ERROR)?;
+
+        // Storage in the single-file layout has no snapshot, so all entries are written
+        // New storage has an empty snapshot, so default entries are written as well
+        storage.snapshot = ids.map(|ids| Snapshot {
+            ids,
+            index,
+            profiles: if exists { storage.profiles.clone() } else { BTreeMap::new() },
+            sites: if exists { storage.sites.clone() } else { BTreeMap::new() },
+        });
+
+        Ok(storage)
+    }
+
+    /// Parses the storage in the single-file shape and migrates it to the current schema version.
+    fn parse(mut data: Value) -> Result<Self> {
+        migrate(&mut data)?;
+        Ok(serde_json::from_value(data)?)
+    }
+
+    /// Saves the storage to the user data directory.
+    ///
+    /// The storage index and each profile and web app are stored in separate
+    /// files. Only changed files are written, each to a temporary file that then
+    /// atomically replaces the old one, so readers never see a partially-written
+    /// file. Files that are replaced are kept as a backup, and all changes
+    /// are recorded to the journal.
+    pub fn write(&self, dirs: &ProjectDirs) -> Result<()> {
+        let _lock = Self::lock(dirs)?;
+
+        let index = self.index().context(STORAGE_SAVE_ERROR)?;
+        let changes = self.changes(&index);
+        let plan = layout::plan(dirs, self).context(STORAGE_SAVE_ERROR)?;
+
+        Self::backup(dirs, &plan.replaced_files())?;
+        layout::write(dirs, self, plan, index).context(STORAGE_SAVE_ERROR)?;
+
+        // The journal is only informational, so failing to record it should not fail the write
+        if let Err(error) = changes.and_then(|changes| Self::record(dirs, &changes)) {
+            warn!("Failed to record storage changes: {error:?}");
+        }
+
+        Ok(())
+    }
+
+    /// Serializes all storage fields, except profiles and web apps.
+    fn index(&self) -> Result<Map<String, Value>> {
+        let Value::Object(mut index) = serde_json::to_value(self)? else {
+            unreachable!("Storage is always serialized as an object");
+        };
+
+        index.remove("profiles");
+        index.remove("sites");
+        Ok(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::write;
+
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    use super::*;
+
+    fn settings_changes(dirs: &ProjectDirs) -> Result<Vec<JournalEntry>> {
+        let history = Storage::history(dirs)?;
+        Ok(history.into_iter().filter(|entry| entry.target == JournalTarget::Settings).collect())
+    }
+
+    #[test]
+    fn new_storage_keeps_default_profile() -> Result<()> {
+        let directory = tempdir()?;
+        let dirs = ProjectDirs {
+            executables: directory.path().into(),
+            sysdata: directory.path().into(),
+            userdata: directory.path().into(),
+        };
+
+        Storage::load(&dirs)?.write(&dirs)?;
+
+        let storage = Storage::load(&dirs)?;
+        assert!(storage.profiles.contains_key(&Ulid::nil()));
+        Ok(())
+    }
+
+    #[test]
+    fn new_storage_does_not_record_default_settings() -> Result<()> {
+        let directory = tempdir()?;
+        let dirs = ProjectDirs {
+            executables: directory.path().into(),
+            sysdata: directory.path().into(),
+            userdata: directory.path().into(),
+        };
+
+        Storage::load(&dirs)?.write(&dirs)?;
+
+        assert!(settings_changes(&dirs)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn missing_settings_are_not_recorded_as_changed() -> Result<()> {
+        let directory = tempdir()?;
+        let dirs = ProjectDirs {
+            executables: directory.path().into(),
+            sysdata: directory.path().into(),
+            userdata: directory.path().into(),
+        };
+
+        // Storage written by an older version, before most settings existed
+        let index = json!({ "version": STORAGE_VERSION, "profiles": [], "sites": [] });
+        write(dirs.userdata.join("config.json"), serde_json::to_vec(&index)?)?;
+
+        Storage::load(&dirs)?.write(&dirs)?;
+        assert!(settings_changes(&dirs)?.is_empty());
+
+        let mut storage = Storage::load(&dirs)?;
+        storage.config.always_patch = true;
+        storage.write(&dirs)?;
+
+        let changes = settings_changes(&dirs)?;
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].fields, ["config.always_patch"]);
+        Ok(())
+    }
+}
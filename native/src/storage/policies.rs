@@ -0,0 +1,264 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::read_to_string;
+
+use anyhow::{Context, Result, bail};
+use log::warn;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::directories::ProjectDirs;
+use crate::storage::Config;
+
+const POLICIES_LOAD_ERROR: &str = "Failed to load system policies";
+
+/// Storage keys outside of [`Config`] that can be controlled by policies.
+const RUNTIME_KEYS: [&str; 2] = ["arguments", "variables"];
+
+/// System-wide policies set by the administrator.
+///
+/// Policies are loaded from `policies.json` in the system data directory.
+/// They provide default values for the config and runtime arguments and
+/// variables, and can lock specific keys so users cannot change them.
+///
+/// Each default value is applied once, when it first appears or changes,
+/// so it also reaches existing storage, and users can change it afterwards.
+#[non_exhaustive]
+#[derive(Deserialize, Debug, Eq, PartialEq, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Policies {
+    /// Default values of the config keys.
+    pub config: Map<String, Value>,
+
+    /// Default arguments to be passed to the Firefox runtime.
+    pub arguments: Option<Vec<String>>,
+
+    /// Default environment variables to be passed to the Firefox runtime.
+    pub variables: Option<BTreeMap<String, String>>,
+
+    /// Keys that cannot be changed by users.
+    ///
+    /// Can contain config keys and the `arguments` and `variables` keys.
+    /// Locked keys always use the value from policies, or the built-in
+    /// default if policies do not provide it.
+    pub locked: BTreeSet<String>,
+}
+
+impl Policies {
+    /// Loads policies from the system data directory.
+    ///
+    /// Invalid policies are ignored with a warning, so a broken
+    /// file does not prevent using the program and launching web apps.
+    pub fn load(dirs: &ProjectDirs) -> Result<Self> {
+        let filename = dirs.sysdata.join("policies.json");
+
+        if !filename.exists() {
+            return Ok(Self::default());
+        }
+
+        let policies = read_to_string(filename)
+            .context(POLICIES_LOAD_ERROR)
+            .and_then(|data| serde_json::from_str::<Self>(&data).context(POLICIES_LOAD_ERROR));
+
+        let policies = match policies {
+            Ok(policies) => policies,
+            Err(error) => {
+                warn!("Ignoring invalid system policies: {error:#}");
+                return Ok(Self::default());
+            }
+        };
+
+        let config_keys = config_keys()?;
+        for key in policies.config.keys().chain(&policies.locked) {
+            if !config_keys.contains_key(key) && !RUNTIME_KEYS.contains(&key.as_str()) {
+                warn!("Ignoring unknown key in system policies: {key}");
+            }
+        }
+
+        Ok(policies)
+    }
+
+    #[inline]
+    pub fn is_locked(&self, key: &str) -> bool {
+        self.locked.contains(key)
+    }
+
+    /// Fails if the key is locked by policies.
+    pub fn ensure_unlocked(&self, key: &str) -> Result<()> {
+        if self.is_locked(key) {
+            bail!("Setting {key} is locked by the system policy");
+        }
+
+        Ok(())
+    }
+
+    /// Fails if the new config changes any locked keys.
+    pub fn check_config(&self, current: &Config, new: &Config) -> Result<()> {
+        let current = serde_json::to_value(current)?;
+        let new = serde_json::to_value(new)?;
+
+        for key in &self.locked {
+            if current.get(key) != new.get(key) {
+                bail!("Setting {key} is locked by the system policy");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Applies policies to the raw storage data.
+    ///
+    /// Default values are only used when they were not applied to the
+    /// storage yet, while locked keys always override the stored values.
+    /// Applied default values are tracked in the `policy_defaults` key.
+    pub(super) fn apply(&self, storage: &mut Value) -> Result<()> {
+        let storage = storage.as_object_mut().context("Storage is not an object")?;
+
+        let mut applied = match storage.remove("policy_defaults") {
+            Some(Value::Object(applied)) => applied,
+            _ => Map::new(),
+        };
+
+        let mut defaults = Map::new();
+        if let Some(arguments) = &self.arguments {
+            defaults.insert("arguments".into(), serde_json::to_value(arguments)?);
+        }
+        if let Some(variables) = &self.variables {
+            defaults.insert("variables".into(), serde_json::to_value(variables)?);
+        }
+        self.apply_keys(storage, RUNTIME_KEYS.into_iter(), &defaults, &mut applied);
+
+        let config = storage
+            .entry("config")
+            .or_insert_with(|| Value::Object(Map::new()))
+            .as_object_mut()
+            .context("Config is not an object")?;
+        let keys = config_keys()?;
+        self.apply_keys(config, keys.keys().map(String::as_str), &self.config, &mut applied);
+
+        storage.insert("policy_defaults".into(), Value::Object(applied));
+        Ok(())
+    }
+
+    fn apply_keys<'a>(
+        &self,
+        target: &mut Map<String, Value>,
+        keys: impl Iterator<Item = &'a str>,
+        defaults: &Map<String, Value>,
+        applied: &mut Map<String, Value>,
+    ) {
+        for key in keys {
+            match defaults.get(key) {
+                Some(value) if self.is_locked(key) => {
+                    target.insert(key.into(), value.clone());
+                }
+                Some(value) if applied.get(key) != Some(value) => {
+                    target.insert(key.into(), value.clone());
+                    applied.insert(key.into(), value.clone());
+                }
+                None if self.is_locked(key) => {
+                    target.remove(key);
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Keys of the config supported on this platform.
+fn config_keys() -> Result<Map<String, Value>> {
+    match serde_json::to_value(Config::default())? {
+        Value::Object(keys) => Ok(keys),
+        _ => unreachable!("Config is always serialized as an object"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn parse(policies: Value) -> Policies {
+        serde_json::from_value(policies).unwrap()
+    }
+
+    #[test]
+    fn locked_keys_override_stored_values() -> Result<()> {
+        let policies = parse(json!({
+            "config": { "always_patch": true },
+            "arguments": ["--locked"],
+            "locked": ["always_patch", "arguments", "runtime_use_xinput2"],
+        }));
+
+        let mut storage = json!({
+            "arguments": ["--user"],
+            "config": { "always_patch": false, "runtime_use_xinput2": true, "runtime_use_portals": true },
+        });
+        policies.apply(&mut storage)?;
+
+        assert_eq!(storage["arguments"], json!(["--locked"]));
+        assert_eq!(storage["config"]["always_patch"], json!(true));
+        assert_eq!(storage["config"]["runtime_use_portals"], json!(true));
+
+        // Locked keys without a value in policies use the built-in default
+        assert!(storage["config"].get("runtime_use_xinput2").is_none());
+
+        // Locked keys are enforced again every time the storage is loaded
+        storage["config"]["always_patch"] = json!(false);
+        policies.apply(&mut storage)?;
+        assert_eq!(storage["config"]["always_patch"], json!(true));
+        Ok(())
+    }
+
+    #[test]
+    fn defaults_are_applied_once() -> Result<()> {
+        let policies = parse(json!({ "config": { "always_patch": true }, "variables": { "A": "1" } }));
+
+        let mut storage = json!({});
+        policies.apply(&mut storage)?;
+        assert_eq!(storage["config"]["always_patch"], json!(true));
+        assert_eq!(storage["variables"], json!({ "A": "1" }));
+
+        // Users can change applied defaults, and their values are kept
+        storage["config"]["always_patch"] = json!(false);
+        storage["variables"] = json!({});
+        policies.apply(&mut storage)?;
+        assert_eq!(storage["config"]["always_patch"], json!(false));
+        assert_eq!(storage["variables"], json!({}));
+
+        // Changed defaults are applied again, but only the keys that changed
+        let policies = parse(json!({ "config": { "always_patch": true }, "variables": { "A": "2" } }));
+        policies.apply(&mut storage)?;
+        assert_eq!(storage["config"]["always_patch"], json!(false));
+        assert_eq!(storage["variables"], json!({ "A": "2" }));
+        Ok(())
+    }
+
+    #[test]
+    fn keys_without_policies_are_untouched() -> Result<()> {
+        let mut storage = json!({ "arguments": ["--user"], "config": { "always_patch": true } });
+        let original = storage.clone();
+
+        Policies::default().apply(&mut storage)?;
+        storage.as_object_mut().unwrap().remove("policy_defaults");
+
+        assert_eq!(storage, original);
+        Ok(())
+    }
+
+    #[test]
+    fn locked_keys_cannot_be_changed() {
+        let policies = parse(json!({ "locked": ["always_patch", "arguments"] }));
+
+        assert!(policies.ensure_unlocked("arguments").is_err());
+        assert!(policies.ensure_unlocked("variables").is_ok());
+
+        let current = Config::default();
+        let mut new = current.clone();
+        new.runtime_use_portals = !new.runtime_use_portals;
+        assert!(policies.check_config(&current, &new).is_ok());
+
+        new.always_patch = !new.always_patch;
+        assert!(policies.check_config(&current, &new).is_err());
+    }
+}
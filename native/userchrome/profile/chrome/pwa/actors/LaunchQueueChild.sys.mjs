@@ -0,0 +1,56 @@
+/**
+ * Provides the `window.launchQueue` API to web app documents.
+ *
+ * See: https://wicg.github.io/web-app-launch/#launchqueue-interface
+ */
+export class LaunchQueueChild extends JSWindowActorChild {
+  consumer = null;
+  launches = [];
+
+  handleEvent (event) {
+    if (event.type !== 'DOMWindowCreated') return;
+
+    const window = this.contentWindow;
+    const launchQueue = Cu.createObjectIn(window, { defineAs: 'launchQueue' });
+    Cu.exportFunction(consumer => this.setConsumer(consumer), launchQueue, { defineAs: 'setConsumer' });
+  }
+
+  receiveMessage (message) {
+    if (message.name !== 'LaunchQueue:Launch') return;
+
+    // Launches are queued until the document sets its consumer
+    this.launches.push(message.data);
+    this.flushLaunches();
+  }
+
+  async setConsumer (consumer) {
+    if (typeof consumer !== 'function') return;
+    this.consumer = consumer;
+
+    // Files of the launch that opened the document are passed before later launches
+    const targetURL = this.document.documentURI;
+    const files = await this.sendQuery('LaunchQueue:Get', { url: targetURL });
+    if (files.length) this.launches.unshift({ targetURL, files });
+
+    this.flushLaunches();
+  }
+
+  flushLaunches () {
+    if (!this.consumer) return;
+
+    const window = this.contentWindow;
+    for (const { targetURL, files } of this.launches.splice(0)) {
+      // Imitates file system handles that only support getting their files
+      const launchParams = {
+        targetURL,
+        files: files.map(file => ({
+          kind: 'file',
+          name: file.name,
+          getFile: () => window.Promise.resolve(file),
+        })),
+      };
+
+      this.consumer(Cu.cloneInto(launchParams, window, { cloneFunctions: true }));
+    }
+  }
+}
@@ -0,0 +1,15 @@
+import { dequeueLaunch, readLaunchFiles } from 'resource://pwa/utils/launchQueue.sys.mjs';
+
+/**
+ * Passes files that the web app was launched with to its documents.
+ */
+export class LaunchQueueParent extends JSWindowActorParent {
+  async receiveMessage (message) {
+    if (message.name !== 'LaunchQueue:Get') return [];
+
+    // Only documents in web app windows can receive launched files
+    if (!this.browsingContext.topChromeWindow?.gFFPWASiteConfig) return [];
+
+    return readLaunchFiles(dequeueLaunch(message.data.url));
+  }
+}
@@ -12,31 +12,137 @@ import { applySystemIntegration } from 'resource://pwa/utils/systemIntegration.s
 const lazy = {};
 
 ChromeUtils.defineESModuleGetters(lazy, {
+  enqueueLaunch: 'resource://pwa/utils/launchQueue.sys.mjs',
+  sendLaunch: 'resource://pwa/utils/launchQueue.sys.mjs',
   sendNativeMessage: 'resource://pwa/utils/nativeMessaging.sys.mjs',
   sanitizeString: 'resource://pwa/utils/common.sys.mjs',
 });
 
 /**
- * Reads the PWAsForFirefox config file and parses it as JSON.
+ * Reads a JSON file and parses it.
  *
- * Function determines config filename based on the current profile directory, and reads it
- * using internal Firefox functions. This relies on specific directory structure, so relocating
- * the profile directory or config file will break config reading.
+ * @param {string} filename - The path of the file.
  *
- * @returns {object} - The config file content as a parsed JSON object.
+ * @returns {object} - The file content as a parsed JSON object.
  */
-function readConfig () {
+function readJson (filename) {
+  const file = Cc['@mozilla.org/file/local;1'].createInstance(Ci.nsIFile);
+  const stream = Cc['@mozilla.org/network/file-input-stream;1'].createInstance(Ci.nsIFileInputStream);
+  file.initWithPath(filename);
+  stream.init(file, 0x01, 0, 0);
+
+  const json = NetUtil.readInputStreamToString(stream, stream.available());
+  stream.close();
+  return JSON.parse(json);
+}
+
+/**
+ * Determines the user data directory based on the current profile directory.
+ *
+ * Profiles are stored in the `profiles` directory inside the user data directory,
+ * so relocating the profile directory will break this.
+ *
+ * @returns {string} - The path of the user data directory.
+ */
+function getUserDataDir () {
   const profileDir = PathUtils.profileDir || Services.dirsvc.get('ProfD', Ci.nsIFile).path;
-  const configDir = PathUtils.parent(PathUtils.parent(profileDir));
-  const configFilename = PathUtils.join(configDir, 'config.json');
+  return PathUtils.parent(PathUtils.parent(profileDir));
+}
+
+/**
+ * Reads the PWAsForFirefox config of a web app.
+ *
+ * Function determines the user data directory based on the current profile directory, and reads
+ * the config file and the web app file using internal Firefox functions. This relies on specific
+ * directory structure, so relocating the profile directory or config file will break config reading.
+ *
+ * Storage written by older versions keeps all web apps in the config file, while newer versions
+ * only list their IDs and store each web app in a separate file inside the `storage` directory.
+ *
+ * @param {string} siteId - The web app ID.
+ *
+ * @returns {object|undefined} - The web app config, or `undefined` if the web app does not exist.
+ */
+function readSiteConfig (siteId) {
+  const userDataDir = getUserDataDir();
+  const config = readJson(PathUtils.join(userDataDir, 'config.json'));
+
+  if (!Array.isArray(config.sites)) return config.sites?.[siteId];
+  if (!config.sites.includes(siteId)) return undefined;
+  return readJson(PathUtils.join(userDataDir, 'storage', 'sites', `${siteId}.json`));
+}
+
+/**
+ * Reads the share target request prepared by the native program and removes it.
+ *
+ * The file contains the content type on the first line, followed by the request body.
+ * Only files inside the `shares` directory of the user data directory are accepted,
+ * so the command line cannot be used to read or remove any other file.
+ *
+ * @param {string} filename - The path of the file.
+ *
+ * @returns {nsIMIMEInputStream} - The request body with its content type, usable as POST data.
+ */
+function readShareRequest (filename) {
+  const directory = Cc['@mozilla.org/file/local;1'].createInstance(Ci.nsIFile);
+  const file = Cc['@mozilla.org/file/local;1'].createInstance(Ci.nsIFile);
+  const stream = Cc['@mozilla.org/network/file-input-stream;1'].createInstance(Ci.nsIFileInputStream);
+  directory.initWithPath(PathUtils.join(getUserDataDir(), 'shares'));
+  file.initWithPath(filename);
+  directory.normalize();
+  file.normalize();
+
+  if (!directory.contains(file) || !file.isFile()) {
+    throw new Error(`Share request is not inside the shares directory: ${filename}`);
+  }
+
+  stream.init(file, 0x01, 0, 0);
+
+  const request = NetUtil.readInputStreamToString(stream, stream.available());
+  stream.close();
+  file.remove(false);
 
-  const configFile = Cc['@mozilla.org/file/local;1'].createInstance(Ci.nsIFile);
-  const configStream = Cc['@mozilla.org/network/file-input-stream;1'].createInstance(Ci.nsIFileInputStream);
-  configFile.initWithPath(configFilename);
-  configStream.init(configFile, 0x01, 0, 0);
+  const separator = request.indexOf('\n');
+  const body = Cc['@mozilla.org/io/string-input-stream;1'].createInstance(Ci.nsIStringInputStream);
+  body.setByteStringData(request.slice(separator + 1));
 
-  const configJson = NetUtil.readInputStreamToString(configStream, configStream.available());
-  return JSON.parse(configJson);
+  const postData = Cc['@mozilla.org/network/mime-input-stream;1'].createInstance(Ci.nsIMIMEInputStream);
+  postData.addHeader('Content-Type', request.slice(0, separator));
+  postData.setData(body);
+  return postData;
+}
+
+/**
+ * Creates browser window arguments that load the URL with the POST data.
+ *
+ * Arguments follow the ones that `openLinkIn` passes to new browser windows,
+ * but primitives need to be wrapped when they are passed as an `nsIArray`.
+ *
+ * @param {string} url - The URL to open.
+ * @param {nsIInputStream} postData - The POST data.
+ * @param {boolean} wrap - Whether to wrap primitives into XPCOM objects.
+ *
+ * @returns {Array} - The window arguments.
+ */
+function createPostArguments (url, postData, wrap) {
+  const wrapPrimitive = (contract, type, data) => {
+    if (!wrap) return data;
+    const primitive = Cc[contract].createInstance(type);
+    primitive.data = data;
+    return primitive;
+  };
+
+  return [
+    wrapPrimitive('@mozilla.org/supports-string;1', Ci.nsISupportsString, url),
+    null, // Extra options
+    null, // Referrer info
+    postData,
+    wrapPrimitive('@mozilla.org/supports-PRBool;1', Ci.nsISupportsPRBool, false), // Allow third-party fixup
+    wrapPrimitive('@mozilla.org/supports-PRUint32;1', Ci.nsISupportsPRUint32, 0), // User context ID
+    null, // Origin principal
+    null, // Origin storage principal
+    Services.scriptSecurityManager.getSystemPrincipal(), // Triggering principal
+  ];
 }
 
 /**
@@ -49,14 +155,34 @@ function readConfig () {
  * @param {object} siteConfig - The web app config.
  * @param {string[]} urlList - The list of URLs to open.
  * @param {boolean} isStartup - If this is the initial launch. Used to attempt to use the `navigator:blank` window.
+ * @param {nsIInputStream?} postData - The POST data of a share target request. Only used with a single URL.
+ * @param {string?} clientMode - The client mode from the web app launch handler. Used when the web app is already opened.
  *
  * @returns {ChromeWindow&Window} - The new window.
  */
-function launchSite (siteConfig, urlList, isStartup) {
+function launchSite (siteConfig, urlList, isStartup, postData, clientMode) {
   // Handle launching a web app when the same web app is already opened
+  // Client modes other than `auto` take precedence over the user settings
+  // Share target requests always open a new window, so their data cannot be lost
+  const existingWindow = !postData && BrowserWindowTracker.orderedWindows.find(win => win.gFFPWASiteConfig?.ulid === siteConfig.ulid);
+
+  if (existingWindow && clientMode === 'navigate-existing') {
+    // Navigate the most recently used window to the first URL and open others as tabs
+    urlList.forEach((url, index) => existingWindow.openTrustedLinkIn(url, index ? 'tab' : 'current', {}));
+    existingWindow.focus();
+    return existingWindow;
+  }
+
+  if (existingWindow && clientMode === 'focus-existing') {
+    // Focus the most recently used window and pass the URLs to its launch queue
+    for (const url of urlList) lazy.sendLaunch(existingWindow.gBrowser.selectedBrowser, url);
+    existingWindow.focus();
+    return existingWindow;
+  }
+
   // We have to specify pref directly as we cannot access ChromeLoader yet
-  const launchType = Services.prefs.getIntPref('firefoxpwa.launchType', 0);
-  if (launchType) {
+  const launchType = clientMode === 'navigate-new' ? 0 : Services.prefs.getIntPref('firefoxpwa.launchType', 0);
+  if (launchType && !postData) {
     for (const win of Services.wm.getEnumerator('navigator:browser')) {
       if (win.gFFPWASiteConfig?.ulid === siteConfig.ulid) {
         for (const url of urlList) {
@@ -102,7 +228,7 @@ function launchSite (siteConfig, urlList, isStartup) {
       // Load the browser chrome and register the window
       const openTime = win.openTime;
       win.location = AppConstants.BROWSER_CHROME_URL;
-      win.arguments = [urlArray];
+      win.arguments = postData ? createPostArguments(urlList[0], postData, false) : [urlArray];
 
       ChromeUtils.addProfilerMarker('earlyBlankWindowVisible', openTime);
       BrowserWindowTracker.registerOpeningWindow(win, false);
@@ -112,7 +238,8 @@ function launchSite (siteConfig, urlList, isStartup) {
 
   // Open a new browser window through the window tracker
   const argsArray = Cc['@mozilla.org/array;1'].createInstance(Ci.nsIMutableArray);
-  argsArray.appendElement(urlArray);
+  if (postData) createPostArguments(urlList[0], postData, true).forEach(arg => argsArray.appendElement(arg));
+  else argsArray.appendElement(urlArray);
   const win = BrowserWindowTracker.openWindow({ args: argsArray });
 
   // Apply the system integration and set the site config
@@ -163,9 +290,9 @@ nsDefaultCommandLineHandler.prototype.handle = function (cmdLine) {
   if (siteId) {
     cmdLine.preventDefault = true;
 
-    let config;
+    let siteConfig;
     try {
-      config = readConfig();
+      siteConfig = readSiteConfig(siteId);
     } catch (error) {
       console.error(error);
       Services.prompt.alert(null, null, 'Failed to load the PWAsForFirefox configuration file.');
@@ -173,23 +300,33 @@ nsDefaultCommandLineHandler.prototype.handle = function (cmdLine) {
       return;
     }
 
-    let siteConfig;
-    try {
-      siteConfig = config.sites[siteId];
-    } catch (_) {
+    if (!siteConfig) {
       Services.prompt.alert(null, null, `No web app installed with requested ULID: ${siteId}\n`);
       Services.wm.getMostRecentWindow('navigator:blank')?.close();
       return;
     }
 
+    // Collect launched files, which follow the file handler URL they should be passed to
+    // This needs to be done before the URLs are handled because that removes them from the arguments
+    const fileList = [];
+    for (let index = 0; index < cmdLine.length - 1; index++) {
+      const argument = cmdLine.getArgument(index);
+      if (argument === '-url') fileList.push([]);
+      if (argument === '-launch-file' && fileList.length) fileList.at(-1).push(cmdLine.getArgument(index + 1));
+    }
+    while (cmdLine.handleFlagWithParam('launch-file', false)) { /* Already collected */ }
+
     // Parse start URLs from the provided arguments
     // Can be used for launching shortcuts or to temporarily overwrite start URL
     const urlList = [];
     let urlArgument;
-    while ((urlArgument = cmdLine.handleFlagWithParam('url', false))) {
+    for (let index = 0; (urlArgument = cmdLine.handleFlagWithParam('url', false)); index++) {
       const fixedUrl = Services.uriFixup.getFixupURIInfo(urlArgument, Services.uriFixup.FIXUP_FLAG_NONE).preferredURI;
       if (fixedUrl.schemeIs('chrome')) continue;
       urlList.push(fixedUrl.spec);
+
+      // Pass launched files to the launch queue of the document opened with the URL
+      if (fileList[index]?.length) lazy.enqueueLaunch(fixedUrl.spec, fileList[index]);
     }
 
     if (!urlList.length) {
@@ -200,13 +337,33 @@ nsDefaultCommandLineHandler.prototype.handle = function (cmdLine) {
       urlList.push(userStartUrl ? userStartUrl : manifestStartUrl);
     }
 
-    launchSite(siteConfig, urlList, isStartup);
+    // Load the share target request prepared by the native program
+    let postData = null;
+    const shareRequest = cmdLine.handleFlagWithParam('share-request', false);
+    if (shareRequest) {
+      try {
+        postData = readShareRequest(shareRequest);
+      } catch (error) {
+        console.error(error);
+        Services.prompt.alert(null, null, 'Failed to load the shared data.');
+      }
+    }
+
+    const clientMode = cmdLine.handleFlagWithParam('client-mode', false);
+    launchSite(siteConfig, urlList, isStartup, postData, clientMode);
     return;
   }
 
   this._handle(cmdLine);
 }
 
+// Provide the launch queue to web app documents, so they can receive launched files
+ChromeUtils.registerWindowActor('FFPWALaunchQueue', {
+  parent: { esModuleURI: 'resource://pwa/actors/LaunchQueueParent.sys.mjs' },
+  child: { esModuleURI: 'resource://pwa/actors/LaunchQueueChild.sys.mjs', events: { DOMWindowCreated: {} } },
+  matches: ['http://*/*', 'https://*/*'],
+});
+
 // Partial fix for reopening web app after closing all windows on macOS (#42)
 // Still does not work when multiple web apps are used in the same profile
 // This does not matter currently because of #81, but once it is fixed, this also needs to be reworked
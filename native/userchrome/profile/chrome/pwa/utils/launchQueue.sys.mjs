@@ -0,0 +1,71 @@
+/**
+ * Files waiting to be passed to the launch queue, keyed by the URL they were launched with.
+ *
+ * Each entry contains file paths of a single launch, so repeated launches
+ * of the same URL are passed to the documents in the order they were opened.
+ *
+ * @type {Map<string, string[][]>}
+ */
+const pendingLaunches = new Map();
+
+/**
+ * Stores files of a launch until the document opened with the URL sets its launch consumer.
+ *
+ * @param {string} url - The file handler action URL.
+ * @param {string[]} paths - The absolute paths of launched files.
+ */
+export function enqueueLaunch (url, paths) {
+  const launches = pendingLaunches.get(url) ?? [];
+  launches.push(paths);
+  pendingLaunches.set(url, launches);
+}
+
+/**
+ * Removes and returns files of the oldest launch of the URL.
+ *
+ * @param {string} url - The URL of the document that sets its launch consumer.
+ *
+ * @returns {string[]} - The absolute paths of launched files, or an empty list if there are none.
+ */
+export function dequeueLaunch (url) {
+  const launches = pendingLaunches.get(url);
+  if (!launches) return [];
+
+  const paths = launches.shift();
+  if (!launches.length) pendingLaunches.delete(url);
+  return paths;
+}
+
+/**
+ * Reads launched files, skipping ones that cannot be read.
+ *
+ * @param {string[]} paths - The absolute paths of launched files.
+ *
+ * @returns {Promise<File[]>} - The launched files.
+ */
+export async function readLaunchFiles (paths) {
+  const files = [];
+  for (const path of paths) {
+    try {
+      files.push(await File.createFromFileName(path));
+    } catch (error) {
+      console.error(`Failed to read launched file ${path}`, error);
+    }
+  }
+  return files;
+}
+
+/**
+ * Passes the launch to the launch queue of the document that is already opened.
+ *
+ * Used when the web app is focused instead of navigated, so the document receives
+ * the launched URL and files of the launch without being reloaded.
+ *
+ * @param {MozBrowser} browser - The browser with the document.
+ * @param {string} url - The launched URL.
+ */
+export async function sendLaunch (browser, url) {
+  const files = await readLaunchFiles(dequeueLaunch(url));
+  const actor = browser.browsingContext?.currentWindowGlobal?.getActor('FFPWALaunchQueue');
+  actor?.sendAsyncMessage('LaunchQueue:Launch', { targetURL: url, files });
+}